@@ -0,0 +1,99 @@
+// Canonical badge ids. Ids are built from slugs rather than display strings, so that names such
+// as "C++", "C#" and "c++ " map to one stable, distinct id, and badges are matched by id only.
+
+pub const LANGUAGE_PREFIX: &str = "lang_";
+pub const AI_EXPERT_PREFIX: &str = "ai_expert_";
+
+// GitHub linguist names whose slug would be ambiguous or that are commonly written another way:
+// (slug, linguist name, aliases). Aliases are compared after slugging, so case and punctuation
+// variants do not need listing.
+const LANGUAGE_ALIASES: &[(&str, &str, &[&str])] = &[
+    ("cpp", "C++", &["c++", "cplusplus", "cxx"]),
+    ("csharp", "C#", &["c#", "cs", "c sharp"]),
+    ("fsharp", "F#", &["f#", "f sharp"]),
+    ("objective_c", "Objective-C", &["objc", "obj-c"]),
+    ("objective_cpp", "Objective-C++", &["objective-c++", "objc++", "obj-c++"]),
+    ("javascript", "JavaScript", &["js", "node", "nodejs", "node.js", "ecmascript"]),
+    ("typescript", "TypeScript", &["ts"]),
+    ("python", "Python", &["py", "python3"]),
+    ("go", "Go", &["golang"]),
+    ("rust", "Rust", &["rs"]),
+    ("ruby", "Ruby", &["rb"]),
+    ("kotlin", "Kotlin", &["kt"]),
+    ("shell", "Shell", &["sh", "bash", "zsh"]),
+    ("powershell", "PowerShell", &["posh", "pwsh"]),
+    ("vim_script", "Vim Script", &["vimscript", "viml", "vim"]),
+    ("emacs_lisp", "Emacs Lisp", &["elisp"]),
+    ("visual_basic_net", "Visual Basic .NET", &["vb.net", "vbnet"]),
+    ("jupyter_notebook", "Jupyter Notebook", &["ipynb", "jupyter"]),
+    ("html", "HTML", &["html5"]),
+    ("css", "CSS", &["css3"]),
+    ("hcl", "HCL", &["terraform"]),
+    ("dockerfile", "Dockerfile", &["docker"]),
+];
+
+/// Lowercase ASCII slug: letters and digits are kept, "+" and "#" are spelled out so "C", "C++"
+/// and "C#" stay distinct, and any other run of characters becomes a single "_"
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.trim().chars() {
+        match c {
+            c if c.is_ascii_alphanumeric() => slug.push(c.to_ascii_lowercase()),
+            '+' => slug.push_str("plus"),
+            '#' => slug.push_str("sharp"),
+            _ if !slug.is_empty() && !slug.ends_with('_') => slug.push('_'),
+            _ => {}
+        }
+    }
+    slug.trim_end_matches('_').to_string()
+}
+
+/// Canonical slug of a language or expertise area, resolving linguist aliases
+pub fn canonical_slug(name: &str) -> String {
+    let slug = slugify(name);
+    LANGUAGE_ALIASES.iter()
+        .find(|(canonical, linguist, aliases)| {
+            *canonical == slug || slugify(linguist) == slug || aliases.iter().any(|a| slugify(a) == slug)
+        })
+        .map(|(canonical, _, _)| canonical.to_string())
+        .unwrap_or(slug)
+}
+
+/// Linguist display name for a language, or the trimmed input when it has no alias entry
+pub fn language_display_name(name: &str) -> String {
+    let slug = canonical_slug(name);
+    LANGUAGE_ALIASES.iter()
+        .find(|(canonical, _, _)| *canonical == slug)
+        .map(|(_, linguist, _)| linguist.to_string())
+        .unwrap_or_else(|| name.trim().to_string())
+}
+
+pub fn same_language(a: &str, b: &str) -> bool {
+    canonical_slug(a) == canonical_slug(b)
+}
+
+pub fn language_badge_id(language: &str) -> String {
+    format!("{}{}", LANGUAGE_PREFIX, canonical_slug(language))
+}
+
+pub fn ai_expert_badge_id(expertise: &str) -> String {
+    format!("{}{}", AI_EXPERT_PREFIX, canonical_slug(expertise))
+}
+
+/// The language or expertise slug a language or AI expertise badge is about
+pub fn badge_subject(badge_id: &str) -> Option<&str> {
+    badge_id.strip_prefix(LANGUAGE_PREFIX)
+        .or_else(|| badge_id.strip_prefix(AI_EXPERT_PREFIX))
+}
+
+/// Canonical form of a possibly legacy badge id, e.g. "lang_c++" becomes "lang_cpp".
+/// Ids of rule, composite, seasonal and other badges are returned unchanged.
+pub fn canonical_badge_id(badge_id: &str) -> String {
+    if let Some(language) = badge_id.strip_prefix(LANGUAGE_PREFIX) {
+        language_badge_id(language)
+    } else if let Some(expertise) = badge_id.strip_prefix(AI_EXPERT_PREFIX) {
+        ai_expert_badge_id(expertise)
+    } else {
+        badge_id.to_string()
+    }
+}
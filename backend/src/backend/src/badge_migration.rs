@@ -0,0 +1,245 @@
+use crate::badge_rules::{self, BadgeRuleSet};
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+const DEFAULT_BATCH_SIZE: u32 = 50;
+const MAX_BATCH_SIZE: u32 = 500;
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BadgeMigrationOptions {
+    pub dry_run: bool,              // only report what would change
+    pub include_up_to_date: bool,   // also re-evaluate profiles already on the current rule set version
+    pub batch_size: Option<u32>,    // profiles per timer tick, defaults to 50
+    pub candidate_rules: Option<BadgeRuleSet>, // preview this rule set instead of the active one; dry runs only
+}
+
+#[derive(Clone, Debug, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum BadgeMigrationState {
+    Running,
+    Completed,
+    Cancelled,
+    Aborted(String),
+}
+
+// How many badges changed (or would change) for one badge id
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct BadgeMigrationDelta {
+    pub badge_id: String,
+    pub gained: u64,
+    pub upgraded: u64,
+    pub downgraded: u64,
+    pub no_longer_met: u64,
+    pub revoked: u64,
+}
+
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct BadgeMigrationReport {
+    pub profiles_scanned: u64,
+    pub profiles_changed: u64,
+    pub profiles_up_to_date: u64,       // skipped, already evaluated under the target version
+    pub profiles_without_analysis: u64, // skipped, no cached analysis to re-evaluate
+    pub badges_gained: u64,
+    pub badges_upgraded: u64,
+    pub badges_downgraded: u64,
+    pub badges_no_longer_met: u64, // kept or put at risk depending on the revocation policy
+    pub badges_revoked: u64,
+    pub by_badge: Vec<BadgeMigrationDelta>,
+}
+
+// Progress of re-evaluating every profile against a badge rule set version
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BadgeMigrationStatus {
+    pub target_version: u32,
+    pub dry_run: bool,
+    pub include_up_to_date: bool,
+    pub batch_size: u32,
+    pub previews_candidate: bool, // evaluated against a candidate rule set that was never stored
+    pub state: BadgeMigrationState,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub cursor: Option<Principal>, // last profile processed
+    pub batches: u32,
+    pub report: BadgeMigrationReport,
+}
+
+// Badge ids affected in one profile's re-evaluation
+pub struct ProfileMigration<'a> {
+    pub gained: &'a [String],
+    pub upgraded: &'a [String],
+    pub downgraded: &'a [String],
+    pub no_longer_met: &'a [String],
+    pub revoked: Vec<&'a str>,
+}
+
+impl ProfileMigration<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.gained.is_empty() && self.upgraded.is_empty() && self.downgraded.is_empty()
+            && self.no_longer_met.is_empty() && self.revoked.is_empty()
+    }
+}
+
+thread_local! {
+    // Driven by timers, which do not survive upgrades either; an interrupted migration is restarted by an admin
+    static MIGRATION: RefCell<Option<BadgeMigrationStatus>> = const { RefCell::new(None) };
+
+    // Rule set previewed by the current dry run, kept out of the status to keep it small
+    static CANDIDATE_RULES: RefCell<Option<BadgeRuleSet>> = const { RefCell::new(None) };
+}
+
+/// Start a migration towards the given rule set version, or a dry run previewing a candidate
+/// rule set drafted against it; only one can run at a time
+pub fn start_migration(options: BadgeMigrationOptions, target_version: u32, now: u64) -> Result<BadgeMigrationStatus, String> {
+    let batch_size = options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+    if batch_size == 0 || batch_size > MAX_BATCH_SIZE {
+        return Err(format!("Batch size must be between 1 and {}", MAX_BATCH_SIZE));
+    }
+
+    let candidate = match options.candidate_rules {
+        Some(_) if !options.dry_run => {
+            return Err("A candidate rule set can only be previewed with a dry run".to_string());
+        }
+        Some(mut candidate) => {
+            badge_rules::validate_rule_set(&candidate)?;
+            // Badges are stamped as if the candidate were the next version
+            candidate.version = target_version + 1;
+            candidate.updated_at = now;
+            Some(candidate)
+        }
+        None => None,
+    };
+
+    MIGRATION.with(|migration| {
+        let mut migration = migration.borrow_mut();
+        if migration.as_ref().is_some_and(|m| m.state == BadgeMigrationState::Running) {
+            return Err("A badge migration is already running".to_string());
+        }
+
+        // Every profile is up to date with the active rules, so a preview always re-evaluates all of them
+        let status = BadgeMigrationStatus {
+            target_version,
+            dry_run: options.dry_run,
+            include_up_to_date: options.include_up_to_date || candidate.is_some(),
+            batch_size,
+            previews_candidate: candidate.is_some(),
+            state: BadgeMigrationState::Running,
+            started_at: now,
+            finished_at: None,
+            cursor: None,
+            batches: 0,
+            report: BadgeMigrationReport::default(),
+        };
+        *migration = Some(status.clone());
+        CANDIDATE_RULES.with(|rules| *rules.borrow_mut() = candidate);
+        Ok(status)
+    })
+}
+
+pub fn get_migration_status() -> Option<BadgeMigrationStatus> {
+    MIGRATION.with(|migration| migration.borrow().clone())
+}
+
+/// The candidate rule set the running dry run previews, if any
+pub fn candidate_rules() -> Option<BadgeRuleSet> {
+    running_migration()
+        .filter(|m| m.previews_candidate)
+        .and_then(|_| CANDIDATE_RULES.with(|rules| rules.borrow().clone()))
+}
+
+/// The running migration, if any
+pub fn running_migration() -> Option<BadgeMigrationStatus> {
+    get_migration_status().filter(|m| m.state == BadgeMigrationState::Running)
+}
+
+/// Stop the running migration; profiles already migrated keep their new badges
+pub fn finish_migration(state: BadgeMigrationState, now: u64) -> Result<BadgeMigrationStatus, String> {
+    MIGRATION.with(|migration| {
+        let mut migration = migration.borrow_mut();
+        match migration.as_mut() {
+            Some(status) if status.state == BadgeMigrationState::Running => {
+                status.state = state;
+                status.finished_at = Some(now);
+                CANDIDATE_RULES.with(|rules| *rules.borrow_mut() = None);
+                Ok(status.clone())
+            }
+            _ => Err("No badge migration is running".to_string()),
+        }
+    })
+}
+
+/// Add one re-evaluated profile to the running migration's report
+pub fn record_profile(changes: &ProfileMigration) {
+    with_running(|status| status.report.add_profile(changes));
+}
+
+/// Add a profile skipped by the migration to the report
+pub fn record_skipped(up_to_date: bool) {
+    with_running(|status| {
+        status.report.profiles_scanned += 1;
+        if up_to_date {
+            status.report.profiles_up_to_date += 1;
+        } else {
+            status.report.profiles_without_analysis += 1;
+        }
+    });
+}
+
+/// Move the cursor past a processed batch
+pub fn record_batch(cursor: Principal) {
+    with_running(|status| {
+        status.batches += 1;
+        status.cursor = Some(cursor);
+    });
+}
+
+fn with_running(f: impl FnOnce(&mut BadgeMigrationStatus)) {
+    MIGRATION.with(|migration| {
+        if let Some(status) = migration.borrow_mut().as_mut().filter(|m| m.state == BadgeMigrationState::Running) {
+            f(status);
+        }
+    });
+}
+
+impl BadgeMigrationReport {
+    /// Add one re-evaluated profile's changes to the totals
+    pub fn add_profile(&mut self, changes: &ProfileMigration) {
+        self.profiles_scanned += 1;
+        if changes.is_empty() {
+            return;
+        }
+        self.profiles_changed += 1;
+
+        self.badges_gained += changes.gained.len() as u64;
+        self.badges_upgraded += changes.upgraded.len() as u64;
+        self.badges_downgraded += changes.downgraded.len() as u64;
+        self.badges_no_longer_met += changes.no_longer_met.len() as u64;
+        self.badges_revoked += changes.revoked.len() as u64;
+
+        for id in changes.gained {
+            self.delta(id).gained += 1;
+        }
+        for id in changes.upgraded {
+            self.delta(id).upgraded += 1;
+        }
+        for id in changes.downgraded {
+            self.delta(id).downgraded += 1;
+        }
+        for id in changes.no_longer_met {
+            self.delta(id).no_longer_met += 1;
+        }
+        for id in &changes.revoked {
+            self.delta(id).revoked += 1;
+        }
+    }
+
+    fn delta(&mut self, badge_id: &str) -> &mut BadgeMigrationDelta {
+        let index = match self.by_badge.binary_search_by(|d| d.badge_id.as_str().cmp(badge_id)) {
+            Ok(index) => index,
+            Err(index) => {
+                self.by_badge.insert(index, BadgeMigrationDelta { badge_id: badge_id.to_string(), ..Default::default() });
+                index
+            }
+        };
+        &mut self.by_badge[index]
+    }
+}
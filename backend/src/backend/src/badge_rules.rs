@@ -0,0 +1,944 @@
+use crate::models::{Badge, BadgeAttribute, BadgeCategory, BadgeEvidence, BadgeMetadata, BadgeTier, GitHubAnalysis, TierProgress};
+use crate::{Memory, BADGE_POLICY_MEMORY_ID, BADGE_RULES_MEMORY_ID, MEMORY_MANAGER};
+use candid::CandidType;
+use ic_stable_structures::{StableCell, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+// Value taken from a GitHub analysis that a badge rule is evaluated against
+#[derive(Clone, Debug, PartialEq, Eq, Hash, CandidType, Serialize, Deserialize)]
+pub enum BadgeMetric {
+    TotalRepos,
+    TotalCommits,
+    TotalStars,
+    TotalForks,
+    Followers,
+    OpenSourceScore,
+    CommunityEngagement,
+    CommitFrequency,
+    CodeQuality,
+    LanguageCount,
+    AccountAgeYears,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct TierThreshold {
+    pub tier: BadgeTier,
+    pub min_value: u32,
+    pub label: String, // e.g. "Regular committer", substituted for {label} in the criteria
+    // Raises the threshold to this percentile of all users' values once the population is large enough
+    pub min_percentile: Option<u32>,
+}
+
+// A data-driven badge definition; the highest tier whose threshold is met is awarded
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BadgeRule {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub category: BadgeCategory,
+    pub metric: BadgeMetric,
+    pub tiers: Vec<TierThreshold>,
+    pub criteria_template: String, // supports {label}, {value}, {tier} and {threshold}
+    pub image_url: String,
+    pub animation_url: Option<String>,
+    pub enabled: bool,
+}
+
+// Which held badges count towards a composite requirement
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum BadgeSelector {
+    BadgeId(String),
+    Category(String),       // "Language", "Contribution", "Achievement" or "Special"
+    Languages(Vec<String>), // language badges for any of these languages
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BadgeRequirement {
+    pub selector: BadgeSelector,
+    pub min_tier: BadgeTier,
+    pub min_count: u32,
+}
+
+// A meta badge earned by holding a combination of other badges; all requirements must be met
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct CompositeBadgeRule {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub tier: BadgeTier,
+    pub requirements: Vec<BadgeRequirement>,
+    pub image_url: String,
+    pub animation_url: Option<String>,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BadgeRuleSet {
+    pub version: u32, // bumped on every admin change
+    pub rules: Vec<BadgeRule>,
+    pub updated_at: u64,
+    pub composites: Option<Vec<CompositeBadgeRule>>, // None on older stored rule sets, treated as the defaults
+}
+
+impl Storable for BadgeRuleSet {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Default for BadgeRuleSet {
+    fn default() -> Self {
+        Self {
+            version: 1,
+            rules: default_badge_rules(),
+            updated_at: 0,
+            composites: Some(default_composite_rules()),
+        }
+    }
+}
+
+// What happens to held badges that a fresh analysis no longer supports
+#[derive(Clone, Debug, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum RevocationMode {
+    Never,            // badges are append-only
+    Downgrade,        // lower the tier to what the data supports, never remove
+    RevokeAfterGrace, // downgrade, and remove badges that stay unearned past the grace period
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BadgeRevocationPolicy {
+    pub mode: RevocationMode,
+    pub grace_period_secs: u64,
+}
+
+impl Default for BadgeRevocationPolicy {
+    fn default() -> Self {
+        Self {
+            mode: RevocationMode::Never,
+            grace_period_secs: 30 * 24 * 3600,
+        }
+    }
+}
+
+impl Storable for BadgeRevocationPolicy {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+const MAX_BADGE_RULES: usize = 100;
+const MAX_COMPOSITE_REQUIREMENTS: usize = 10;
+const CATEGORY_NAMES: [&str; 4] = ["Language", "Contribution", "Achievement", "Special"];
+const RESERVED_ID_PREFIXES: [&str; 3] = [crate::badge_ids::LANGUAGE_PREFIX, "ai_", "season_"];
+const MIN_PERCENTILE_POPULATION: usize = 50; // below this, percentile tiers use their fixed min_value
+const MAX_GRACE_PERIOD_SECS: u64 = 365 * 24 * 3600;
+
+thread_local! {
+    static BADGE_RULES: RefCell<StableCell<BadgeRuleSet, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(BADGE_RULES_MEMORY_ID)),
+            BadgeRuleSet::default(),
+        ).expect("Failed to initialize badge rules")
+    );
+
+    // Sorted population values for metrics with percentile tiers; rebuilt from cached analyses by timer
+    static METRIC_DISTRIBUTIONS: RefCell<(u64, HashMap<BadgeMetric, Vec<u32>>)> = RefCell::new((0, HashMap::new()));
+
+    static REVOCATION_POLICY: RefCell<StableCell<BadgeRevocationPolicy, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(BADGE_POLICY_MEMORY_ID)),
+            BadgeRevocationPolicy::default(),
+        ).expect("Failed to initialize badge revocation policy")
+    );
+}
+
+pub fn get_revocation_policy() -> BadgeRevocationPolicy {
+    REVOCATION_POLICY.with(|cell| cell.borrow().get().clone())
+}
+
+pub fn set_revocation_policy(policy: BadgeRevocationPolicy) -> Result<(), String> {
+    if policy.grace_period_secs > MAX_GRACE_PERIOD_SECS {
+        return Err(format!("Grace period cannot exceed {} seconds", MAX_GRACE_PERIOD_SECS));
+    }
+
+    REVOCATION_POLICY.with(|cell| {
+        cell.borrow_mut().set(policy)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to store badge revocation policy: {:?}", e))
+    })
+}
+
+pub fn get_badge_rules() -> BadgeRuleSet {
+    BADGE_RULES.with(|cell| cell.borrow().get().clone())
+}
+
+/// Add a rule, or replace the rule with the same id
+pub fn upsert_badge_rule(rule: BadgeRule) -> Result<BadgeRuleSet, String> {
+    validate_badge_rule(&rule)?;
+
+    let mut rule_set = get_badge_rules();
+    if rule_set.composites.get_or_insert_with(default_composite_rules).iter().any(|c| c.id == rule.id) {
+        return Err(format!("Badge id '{}' is already used by a composite badge", rule.id));
+    }
+
+    match rule_set.rules.iter_mut().find(|r| r.id == rule.id) {
+        Some(existing) => *existing = rule,
+        None => {
+            if rule_set.rules.len() >= MAX_BADGE_RULES {
+                return Err(format!("At most {} badge rules are allowed", MAX_BADGE_RULES));
+            }
+            rule_set.rules.push(rule);
+        }
+    }
+
+    store_badge_rules(rule_set)
+}
+
+pub fn remove_badge_rule(id: &str) -> Result<BadgeRuleSet, String> {
+    let mut rule_set = get_badge_rules();
+    let before = rule_set.rules.len();
+    rule_set.rules.retain(|r| r.id != id);
+    if rule_set.rules.len() == before {
+        return Err(format!("Badge rule '{}' not found", id));
+    }
+
+    store_badge_rules(rule_set)
+}
+
+/// Add a composite badge, or replace the one with the same id
+pub fn upsert_composite_rule(composite: CompositeBadgeRule) -> Result<BadgeRuleSet, String> {
+    validate_composite_rule(&composite)?;
+
+    let mut rule_set = get_badge_rules();
+    if rule_set.rules.iter().any(|r| r.id == composite.id) {
+        return Err(format!("Badge id '{}' is already used by a badge rule", composite.id));
+    }
+
+    let composites = rule_set.composites.get_or_insert_with(default_composite_rules);
+    match composites.iter_mut().find(|c| c.id == composite.id) {
+        Some(existing) => *existing = composite,
+        None => {
+            if composites.len() >= MAX_BADGE_RULES {
+                return Err(format!("At most {} composite badges are allowed", MAX_BADGE_RULES));
+            }
+            composites.push(composite);
+        }
+    }
+
+    store_badge_rules(rule_set)
+}
+
+pub fn remove_composite_rule(id: &str) -> Result<BadgeRuleSet, String> {
+    let mut rule_set = get_badge_rules();
+    let composites = rule_set.composites.get_or_insert_with(default_composite_rules);
+    let before = composites.len();
+    composites.retain(|c| c.id != id);
+    if composites.len() == before {
+        return Err(format!("Composite badge '{}' not found", id));
+    }
+
+    store_badge_rules(rule_set)
+}
+
+/// Replace all rules with the built-in defaults
+pub fn reset_badge_rules() -> Result<BadgeRuleSet, String> {
+    store_badge_rules(BadgeRuleSet::default())
+}
+
+fn store_badge_rules(mut rule_set: BadgeRuleSet) -> Result<BadgeRuleSet, String> {
+    rule_set.version = get_badge_rules().version + 1;
+    rule_set.updated_at = ic_cdk::api::time();
+
+    BADGE_RULES.with(|cell| {
+        cell.borrow_mut().set(rule_set.clone())
+            .map_err(|e| format!("Failed to store badge rules: {:?}", e))
+    })?;
+
+    Ok(rule_set)
+}
+
+pub fn validate_badge_identity(id: &str, name: &str, image_url: &str) -> Result<(), String> {
+    if id.is_empty() || id.len() > 64 {
+        return Err("Rule id must be between 1 and 64 characters".to_string());
+    }
+    if crate::badge_ids::slugify(id) != id {
+        return Err("Rule id must be a slug of lowercase letters and digits separated by single underscores".to_string());
+    }
+    if name.trim().is_empty() {
+        return Err("Rule name cannot be empty".to_string());
+    }
+    if !image_url.starts_with('/') && !image_url.starts_with("https://") {
+        return Err("Image URL must be a path or an https URL".to_string());
+    }
+
+    Ok(())
+}
+
+/// Rule and composite ids must not use the prefixes of generated badge ids
+fn validate_unreserved_id(id: &str) -> Result<(), String> {
+    match RESERVED_ID_PREFIXES.iter().find(|prefix| id.starts_with(*prefix)) {
+        Some(prefix) => Err(format!("Badge ids starting with '{}' are reserved", prefix)),
+        None => Ok(()),
+    }
+}
+
+pub fn validate_badge_rule(rule: &BadgeRule) -> Result<(), String> {
+    validate_badge_identity(&rule.id, &rule.name, &rule.image_url)?;
+    validate_unreserved_id(&rule.id)?;
+    if rule.criteria_template.trim().is_empty() {
+        return Err("Criteria template cannot be empty".to_string());
+    }
+    validate_tiers(&rule.tiers)
+}
+
+/// Tiers must be non-empty, ordered and labelled, with thresholds that climb with the tier
+pub fn validate_tiers(tiers: &[TierThreshold]) -> Result<(), String> {
+    if tiers.is_empty() {
+        return Err("At least one tier is required".to_string());
+    }
+
+    for pair in tiers.windows(2) {
+        if pair[1].tier <= pair[0].tier {
+            return Err(format!(
+                "Tier {} must be higher than {}",
+                pair[1].tier.get_display_name(),
+                pair[0].tier.get_display_name()
+            ));
+        }
+        if pair[1].min_value <= pair[0].min_value {
+            return Err(format!(
+                "Threshold for {} must be greater than {}",
+                pair[1].tier.get_display_name(),
+                pair[0].min_value
+            ));
+        }
+    }
+
+    if tiers.iter().any(|t| t.label.trim().is_empty()) {
+        return Err("Every tier needs a label".to_string());
+    }
+
+    let percentiles: Vec<u32> = tiers.iter().filter_map(|t| t.min_percentile).collect();
+    if percentiles.iter().any(|p| *p > 100) {
+        return Err("Percentiles must be between 0 and 100".to_string());
+    }
+    if percentiles.windows(2).any(|pair| pair[1] <= pair[0]) {
+        return Err("Percentiles must increase with the tier".to_string());
+    }
+
+    Ok(())
+}
+
+/// Validate a whole rule set, e.g. a candidate previewed before any of it is applied
+pub fn validate_rule_set(rule_set: &BadgeRuleSet) -> Result<(), String> {
+    let composites = rule_set.composites.as_deref().unwrap_or_default();
+    if rule_set.rules.len() > MAX_BADGE_RULES || composites.len() > MAX_BADGE_RULES {
+        return Err(format!("At most {} badge rules and {} composite badges are allowed", MAX_BADGE_RULES, MAX_BADGE_RULES));
+    }
+
+    let mut ids = HashSet::new();
+    for rule in &rule_set.rules {
+        validate_badge_rule(rule).map_err(|e| format!("Badge rule '{}': {}", rule.id, e))?;
+        if !ids.insert(rule.id.as_str()) {
+            return Err(format!("Badge id '{}' is used more than once", rule.id));
+        }
+    }
+    for composite in composites {
+        validate_composite_rule(composite).map_err(|e| format!("Composite badge '{}': {}", composite.id, e))?;
+        if !ids.insert(composite.id.as_str()) {
+            return Err(format!("Badge id '{}' is used more than once", composite.id));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn validate_composite_rule(composite: &CompositeBadgeRule) -> Result<(), String> {
+    validate_badge_identity(&composite.id, &composite.name, &composite.image_url)?;
+    validate_unreserved_id(&composite.id)?;
+    if composite.requirements.is_empty() || composite.requirements.len() > MAX_COMPOSITE_REQUIREMENTS {
+        return Err(format!("Composite badges need between 1 and {} requirements", MAX_COMPOSITE_REQUIREMENTS));
+    }
+
+    for requirement in &composite.requirements {
+        if requirement.min_count == 0 {
+            return Err("Requirement count must be at least 1".to_string());
+        }
+        match &requirement.selector {
+            BadgeSelector::BadgeId(id) if id == &composite.id => {
+                return Err("A composite badge cannot require itself".to_string());
+            }
+            BadgeSelector::Category(name) if !CATEGORY_NAMES.contains(&name.as_str()) => {
+                return Err(format!("Unknown category '{}', expected one of {:?}", name, CATEGORY_NAMES));
+            }
+            BadgeSelector::Languages(languages) if languages.is_empty() => {
+                return Err("Language requirements need at least one language".to_string());
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Evaluate all enabled rules of a rule set against an analysis
+pub fn evaluate_badge_rules(rule_set: &BadgeRuleSet, analysis: &GitHubAnalysis, current_time: u64) -> Vec<Badge> {
+    rule_set.rules.iter()
+        .filter(|rule| rule.enabled)
+        .filter_map(|rule| evaluate_rule(rule, analysis, current_time))
+        .collect()
+}
+
+/// Progress towards the next tier of every enabled rule, including rules not yet earned
+pub fn evaluate_rule_progress(analysis: &GitHubAnalysis) -> Vec<TierProgress> {
+    METRIC_DISTRIBUTIONS.with(|d| {
+        let d = d.borrow();
+        get_badge_rules().rules.iter()
+            .filter(|rule| rule.enabled)
+            .map(|rule| {
+                let population = d.1.get(&rule.metric);
+                let thresholds: Vec<(BadgeTier, u32)> = rule.tiers.iter()
+                    .map(|t| (t.tier.clone(), tier_threshold(t, population).0))
+                    .collect();
+                tier_progress(&rule.id, &rule.name, metric_value(&rule.metric, analysis), &thresholds)
+            })
+            .collect()
+    })
+}
+
+/// Value each tier of a rule currently requires, with the percentile applied if any
+pub fn rule_thresholds(rule: &BadgeRule) -> Vec<(u32, Option<u32>)> {
+    METRIC_DISTRIBUTIONS.with(|d| {
+        let population = d.borrow().1.get(&rule.metric).cloned();
+        rule.tiers.iter().map(|t| tier_threshold(t, population.as_ref())).collect()
+    })
+}
+
+/// Criteria text for a tier as it reads when the threshold is exactly met
+pub fn describe_tier(rule: &BadgeRule, threshold: &TierThreshold, required: u32) -> String {
+    rule.criteria_template
+        .replace("{label}", &threshold.label)
+        .replace("{value}", &required.to_string())
+        .replace("{tier}", threshold.tier.get_display_name())
+        .replace("{threshold}", &required.to_string())
+}
+
+pub fn describe_requirement(requirement: &BadgeRequirement) -> String {
+    let badges = match &requirement.selector {
+        BadgeSelector::BadgeId(id) => format!("the {} badge", id),
+        BadgeSelector::Category(name) => format!("{} {} badge(s)", requirement.min_count, name),
+        BadgeSelector::Languages(languages) => format!("{} language badge(s) among {}", requirement.min_count, languages.join(", ")),
+    };
+    format!("Hold {} at {} or higher", badges, requirement.min_tier.get_display_name())
+}
+
+/// Place a value on a tier table ordered from the lowest tier up
+pub fn tier_progress(badge_id: &str, name: &str, value: u32, thresholds: &[(BadgeTier, u32)]) -> TierProgress {
+    let current_tier = thresholds.iter().rev()
+        .find(|(_, required)| value >= *required)
+        .map(|(tier, _)| tier.clone());
+    let next = thresholds.iter().find(|(_, required)| value < *required);
+
+    TierProgress {
+        badge_id: badge_id.to_string(),
+        name: name.to_string(),
+        current_tier,
+        current_value: value,
+        next_tier: next.map(|(tier, _)| tier.clone()),
+        next_threshold: next.map(|(_, required)| *required),
+        percent: match next {
+            Some((_, required)) => (value as f32 / *required as f32 * 100.0).min(100.0),
+            None => 100.0,
+        },
+    }
+}
+
+// Population values for the percentile tiers of some rule sets, accumulated over batches of analyses
+pub struct DistributionCounts {
+    metrics: Vec<BadgeMetric>,
+    values: HashMap<BadgeMetric, Vec<u32>>,
+}
+
+impl DistributionCounts {
+    pub fn new(rule_sets: &[&BadgeRuleSet]) -> Self {
+        let mut metrics: Vec<BadgeMetric> = Vec::new();
+        for rule in rule_sets.iter().flat_map(|rule_set| rule_set.rules.iter()) {
+            if rule.enabled && rule.tiers.iter().any(|t| t.min_percentile.is_some()) && !metrics.contains(&rule.metric) {
+                metrics.push(rule.metric.clone());
+            }
+        }
+        Self { metrics, values: HashMap::new() }
+    }
+
+    pub fn add_analyses(&mut self, analyses: impl Iterator<Item = GitHubAnalysis>) {
+        for analysis in analyses {
+            for metric in &self.metrics {
+                self.values.entry(metric.clone()).or_default().push(metric_value(metric, &analysis));
+            }
+        }
+    }
+}
+
+/// Replace the population distributions used by the percentile tiers with the counted ones
+pub fn set_metric_distributions(counts: DistributionCounts, now: u64) {
+    let mut distributions = counts.values;
+    for values in distributions.values_mut() {
+        values.sort_unstable();
+    }
+
+    METRIC_DISTRIBUTIONS.with(|d| *d.borrow_mut() = (now, distributions));
+}
+
+/// Value a tier requires: its fixed minimum, raised to its percentile of the population when known
+fn tier_threshold(tier: &TierThreshold, population: Option<&Vec<u32>>) -> (u32, Option<u32>) {
+    match (tier.min_percentile, population) {
+        (Some(percentile), Some(values)) if values.len() >= MIN_PERCENTILE_POPULATION => {
+            let index = (values.len() * percentile as usize / 100).min(values.len() - 1);
+            (values[index].max(tier.min_value), Some(percentile))
+        }
+        _ => (tier.min_value, None),
+    }
+}
+
+fn evaluate_rule(rule: &BadgeRule, analysis: &GitHubAnalysis, current_time: u64) -> Option<Badge> {
+    let value = metric_value(&rule.metric, analysis);
+    let (threshold, (required, percentile)) = METRIC_DISTRIBUTIONS.with(|d| {
+        let d = d.borrow();
+        let population = d.1.get(&rule.metric);
+        rule.tiers.iter().rev()
+            .map(|t| (t, tier_threshold(t, population)))
+            .find(|(_, (required, _))| value >= *required)
+    })?;
+
+    let mut criteria = rule.criteria_template
+        .replace("{label}", &threshold.label)
+        .replace("{value}", &value.to_string())
+        .replace("{tier}", threshold.tier.get_display_name())
+        .replace("{threshold}", &required.to_string());
+    if let Some(percentile) = percentile {
+        criteria.push_str(&format!(" (top {}% of developers)", 100 - percentile));
+    }
+
+    Some(Badge {
+        id: rule.id.clone(),
+        name: rule.name.clone(),
+        description: rule.description.clone(),
+        category: rule.category.clone(),
+        tier: threshold.tier.clone(),
+        earned_at: current_time,
+        criteria_met: vec![criteria],
+        score_achieved: value,
+        upgraded_at: None,
+        tier_history: None,
+        nft_token_id: None,
+        at_risk_since: None,
+        season: None,
+        rule_version: None,
+        last_confirmed_at: None,
+        evidence: Some(BadgeEvidence::from_inputs(&[
+            ("rule", rule.id.clone()),
+            ("metric", format!("{:?}", rule.metric)),
+            ("value", value.to_string()),
+            ("threshold", required.to_string()),
+        ])),
+        metadata: BadgeMetadata {
+            image_url: rule.image_url.clone(),
+            animation_url: rule.animation_url.clone(),
+            attributes: vec![
+                BadgeAttribute {
+                    trait_type: "Category".to_string(),
+                    value: category_name(&rule.category).to_string(),
+                    display_type: None,
+                },
+                BadgeAttribute {
+                    trait_type: "Score".to_string(),
+                    value: value.to_string(),
+                    display_type: Some("number".to_string()),
+                },
+                BadgeAttribute {
+                    trait_type: "Tier".to_string(),
+                    value: threshold.tier.get_display_name().to_string(),
+                    display_type: None,
+                },
+            ],
+            rarity_score: threshold.tier.get_points(),
+        },
+    })
+}
+
+pub fn composite_badge_ids(rule_set: &BadgeRuleSet) -> HashSet<String> {
+    rule_set.composites.clone().unwrap_or_else(default_composite_rules).into_iter().map(|c| c.id).collect()
+}
+
+/// Evaluate a rule set's composite badges against the badges a user holds. Only non-composite
+/// badges count towards requirements, and the contributing badges are listed as evidence.
+pub fn evaluate_composite_badges(rule_set: &BadgeRuleSet, held: &[Badge], current_time: u64) -> Vec<Badge> {
+    let composites = rule_set.composites.clone().unwrap_or_else(default_composite_rules);
+    let base: Vec<&Badge> = held.iter()
+        .filter(|b| !composites.iter().any(|c| c.id == b.id))
+        .collect();
+
+    composites.iter()
+        .filter(|composite| composite.enabled)
+        .filter_map(|composite| evaluate_composite(composite, &base, current_time))
+        .collect()
+}
+
+fn evaluate_composite(composite: &CompositeBadgeRule, base: &[&Badge], current_time: u64) -> Option<Badge> {
+    let mut contributing: Vec<&Badge> = Vec::new();
+
+    for requirement in &composite.requirements {
+        let matching: Vec<&Badge> = base.iter()
+            .copied()
+            .filter(|b| b.tier >= requirement.min_tier && selector_matches(&requirement.selector, b))
+            .filter(|b| !contributing.iter().any(|c| c.id == b.id))
+            .take(requirement.min_count as usize)
+            .collect();
+
+        if (matching.len() as u32) < requirement.min_count {
+            return None;
+        }
+        contributing.extend(matching);
+    }
+
+    let criteria_met = contributing.iter()
+        .map(|b| format!("Holds {} ({})", b.name, b.tier.get_display_name()))
+        .collect();
+    let evidence_inputs: Vec<(&str, String)> = std::iter::once(("composite", composite.id.clone()))
+        .chain(contributing.iter().map(|b| ("contributing_badge", format!("{}:{:?}", b.id, b.tier))))
+        .collect();
+    let contributing_ids = contributing.iter()
+        .map(|b| b.id.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    Some(Badge {
+        id: composite.id.clone(),
+        name: composite.name.clone(),
+        description: composite.description.clone(),
+        category: BadgeCategory::Special("Composite".to_string()),
+        tier: composite.tier.clone(),
+        earned_at: current_time,
+        criteria_met,
+        score_achieved: contributing.len() as u32,
+        upgraded_at: None,
+        tier_history: None,
+        nft_token_id: None,
+        at_risk_since: None,
+        season: None,
+        rule_version: None,
+        last_confirmed_at: None,
+        evidence: Some(BadgeEvidence::from_inputs(&evidence_inputs)),
+        metadata: BadgeMetadata {
+            image_url: composite.image_url.clone(),
+            animation_url: composite.animation_url.clone(),
+            attributes: vec![
+                BadgeAttribute {
+                    trait_type: "Category".to_string(),
+                    value: "Composite".to_string(),
+                    display_type: None,
+                },
+                BadgeAttribute {
+                    trait_type: "Contributing Badges".to_string(),
+                    value: contributing_ids,
+                    display_type: None,
+                },
+                BadgeAttribute {
+                    trait_type: "Tier".to_string(),
+                    value: composite.tier.get_display_name().to_string(),
+                    display_type: None,
+                },
+            ],
+            rarity_score: composite.tier.get_points(),
+        },
+    })
+}
+
+fn selector_matches(selector: &BadgeSelector, badge: &Badge) -> bool {
+    match selector {
+        BadgeSelector::BadgeId(id) => badge.id == crate::badge_ids::canonical_badge_id(id),
+        BadgeSelector::Category(name) => category_name(&badge.category) == name,
+        BadgeSelector::Languages(languages) => match &badge.category {
+            BadgeCategory::Language(language) => languages.iter().any(|l| crate::badge_ids::same_language(l, language)),
+            _ => false,
+        },
+    }
+}
+
+pub fn metric_value(metric: &BadgeMetric, analysis: &GitHubAnalysis) -> u32 {
+    match metric {
+        BadgeMetric::TotalRepos => analysis.total_repos,
+        BadgeMetric::TotalCommits => analysis.total_commits,
+        BadgeMetric::TotalStars => analysis.total_stars_received,
+        BadgeMetric::TotalForks => analysis.total_forks_received,
+        BadgeMetric::Followers => analysis.followers,
+        BadgeMetric::OpenSourceScore => crate::utils::calculate_open_source_score(analysis),
+        BadgeMetric::CommunityEngagement => analysis.community_engagement_score as u32,
+        BadgeMetric::CommitFrequency => analysis.commit_frequency_score as u32,
+        BadgeMetric::CodeQuality => analysis.code_quality_score as u32,
+        BadgeMetric::LanguageCount => crate::utils::extract_languages_from_repos(&analysis.repositories).len() as u32,
+        BadgeMetric::AccountAgeYears => analysis.account_age_days / 365,
+    }
+}
+
+fn category_name(category: &BadgeCategory) -> &'static str {
+    match category {
+        BadgeCategory::Language(_) => "Language",
+        BadgeCategory::Contribution(_) => "Contribution",
+        BadgeCategory::Achievement(_) => "Achievement",
+        BadgeCategory::Special(_) => "Special",
+    }
+}
+
+fn tiers(levels: &[(BadgeTier, u32, &str)]) -> Vec<TierThreshold> {
+    levels.iter()
+        .map(|(tier, min_value, label)| TierThreshold {
+            tier: tier.clone(),
+            min_value: *min_value,
+            label: label.to_string(),
+            min_percentile: None,
+        })
+        .collect()
+}
+
+fn percentile_tiers(levels: &[(BadgeTier, u32, u32, &str)]) -> Vec<TierThreshold> {
+    levels.iter()
+        .map(|(tier, min_value, percentile, label)| TierThreshold {
+            tier: tier.clone(),
+            min_value: *min_value,
+            label: label.to_string(),
+            min_percentile: Some(*percentile),
+        })
+        .collect()
+}
+
+fn rule(
+    id: &str,
+    name: &str,
+    description: &str,
+    category: BadgeCategory,
+    metric: BadgeMetric,
+    tiers: Vec<TierThreshold>,
+    criteria_template: &str,
+) -> BadgeRule {
+    let folder = match category {
+        BadgeCategory::Language(_) => "languages",
+        BadgeCategory::Contribution(_) => "contributions",
+        BadgeCategory::Achievement(_) => "achievements",
+        BadgeCategory::Special(_) => "special",
+    };
+
+    BadgeRule {
+        id: id.to_string(),
+        name: name.to_string(),
+        description: description.to_string(),
+        category,
+        metric,
+        tiers,
+        criteria_template: criteria_template.to_string(),
+        image_url: format!("/badges/{}/{}.svg", folder, id),
+        animation_url: Some(format!("/badges/{}/{}_animated.gif", folder, id)),
+        enabled: true,
+    }
+}
+
+/// Built-in rules, seeded on first install and restored by `reset_badge_rules`
+pub fn default_badge_rules() -> Vec<BadgeRule> {
+    use BadgeTier::*;
+
+    // Engagement and consistency scores are capped at 100, so their tables span 0-100;
+    // the uncapped open source score gets a wider table. Percentiles apply once enough
+    // users have been analyzed.
+    let bounded_score_tiers = [
+        (Bronze1, 10, 10, "Getting started"),
+        (Bronze2, 20, 25, "Regular contributor"),
+        (Bronze3, 30, 40, "Active participant"),
+        (Silver1, 40, 55, "Dedicated contributor"),
+        (Silver2, 50, 70, "Community leader"),
+        (Silver3, 60, 80, "Influential member"),
+        (Gold1, 70, 90, "Community champion"),
+        (Gold2, 80, 95, "Elite contributor"),
+        (Gold3, 90, 99, "Legendary figure"),
+    ];
+
+    vec![
+        rule(
+            "open_source_contributor",
+            "Open Source Contributor",
+            "Active contributor to open source projects",
+            BadgeCategory::Contribution("OpenSource".to_string()),
+            BadgeMetric::OpenSourceScore,
+            percentile_tiers(&[
+                (Bronze1, 10, 10, "Getting started"),
+                (Bronze2, 30, 25, "Regular contributor"),
+                (Bronze3, 60, 40, "Active participant"),
+                (Silver1, 100, 55, "Dedicated contributor"),
+                (Silver2, 175, 70, "Community leader"),
+                (Silver3, 300, 80, "Influential member"),
+                (Gold1, 500, 90, "Community champion"),
+                (Gold2, 900, 95, "Elite contributor"),
+                (Gold3, 1500, 99, "Legendary figure"),
+            ]),
+            "{label} - open source score {value} (5 per public repo + 2 per fork + 1 per star), {tier} requires {threshold}",
+        ),
+        rule(
+            "community_builder",
+            "Community Builder",
+            "Building and engaging with the developer community",
+            BadgeCategory::Contribution("Community".to_string()),
+            BadgeMetric::CommunityEngagement,
+            percentile_tiers(&bounded_score_tiers),
+            "{label} - community engagement {value} on a 0-100 scale, {tier} requires {threshold}",
+        ),
+        rule(
+            "consistent_contributor",
+            "Consistent Contributor",
+            "Maintaining consistent contribution patterns",
+            BadgeCategory::Contribution("Consistency".to_string()),
+            BadgeMetric::CommitFrequency,
+            percentile_tiers(&bounded_score_tiers),
+            "{label} - commit frequency {value} on a 0-100 scale, {tier} requires {threshold}",
+        ),
+        rule(
+            "repository_creator",
+            "Repository Creator",
+            "Creating and maintaining multiple repositories",
+            BadgeCategory::Achievement("Repository Creator".to_string()),
+            BadgeMetric::TotalRepos,
+            tiers(&[
+                (Bronze1, 5, "Created first repositories"),
+                (Bronze2, 11, "Regular repository creator"),
+                (Bronze3, 26, "Prolific project starter"),
+                (Silver1, 51, "Dedicated builder"),
+                (Silver2, 101, "Project factory"),
+                (Silver3, 201, "Repository master"),
+                (Gold1, 301, "Elite creator"),
+                (Gold2, 501, "Legendary builder"),
+                (Gold3, 1001, "Repository titan"),
+            ]),
+            "{label} - {value} repositories created",
+        ),
+        rule(
+            "commit_master",
+            "Commit Master",
+            "Accumulating significant commit history",
+            BadgeCategory::Achievement("Commit Master".to_string()),
+            BadgeMetric::TotalCommits,
+            tiers(&[
+                (Bronze1, 100, "Getting into the rhythm"),
+                (Bronze2, 501, "Regular committer"),
+                (Bronze3, 1001, "Consistent contributor"),
+                (Silver1, 2501, "Dedicated coder"),
+                (Silver2, 5001, "Prolific committer"),
+                (Silver3, 10001, "Commit machine"),
+                (Gold1, 25001, "Elite contributor"),
+                (Gold2, 50001, "Legendary committer"),
+                (Gold3, 100001, "Commit titan"),
+            ]),
+            "{label} - {value} commits made",
+        ),
+        rule(
+            "star_collector",
+            "Star Collector",
+            "Creating popular and useful projects",
+            BadgeCategory::Achievement("Star Collector".to_string()),
+            BadgeMetric::TotalStars,
+            tiers(&[
+                (Bronze1, 10, "Gaining recognition"),
+                (Bronze2, 51, "Community appreciated"),
+                (Bronze3, 151, "Popular creator"),
+                (Silver1, 301, "Widely recognized"),
+                (Silver2, 751, "Highly regarded"),
+                (Silver3, 1501, "Community favorite"),
+                (Gold1, 3001, "Influential creator"),
+                (Gold2, 7501, "Legendary developer"),
+                (Gold3, 15001, "Star magnet"),
+            ]),
+            "{label} - {value} stars received",
+        ),
+        rule(
+            "early_adopter",
+            "Early Adopter",
+            "Been on GitHub for over 5 years",
+            BadgeCategory::Special("EarlyAdopter".to_string()),
+            BadgeMetric::AccountAgeYears,
+            tiers(&[(Gold2, 5, "Long-time member")]),
+            "Account age > 5 years",
+        ),
+        rule(
+            "polyglot",
+            "Polyglot",
+            "Codes in many different programming languages",
+            BadgeCategory::Special("Polyglot".to_string()),
+            BadgeMetric::LanguageCount,
+            tiers(&[
+                (Bronze3, 5, "Multilingual"),
+                (Silver2, 8, "Versatile"),
+                (Gold1, 13, "Polyglot"),
+            ]),
+            "Uses {value} programming languages",
+        ),
+        rule(
+            "innovator",
+            "Innovator",
+            "Consistently produces high-quality code",
+            BadgeCategory::Special("Innovation".to_string()),
+            BadgeMetric::CodeQuality,
+            tiers(&[(Gold3, 80, "High code quality")]),
+            "Code quality score > 80",
+        ),
+    ]
+}
+
+/// Built-in composite badges
+pub fn default_composite_rules() -> Vec<CompositeBadgeRule> {
+    let frontend = ["JavaScript", "TypeScript", "HTML", "CSS", "Vue", "Svelte", "Dart"];
+    let backend = ["Rust", "Go", "Java", "Python", "C#", "Kotlin", "Ruby", "PHP", "Elixir", "Scala", "C++"];
+    let languages = |names: &[&str]| BadgeSelector::Languages(names.iter().map(|n| n.to_string()).collect());
+    let category = |name: &str| BadgeRequirement {
+        selector: BadgeSelector::Category(name.to_string()),
+        min_tier: BadgeTier::Silver1,
+        min_count: 1,
+    };
+
+    vec![
+        CompositeBadgeRule {
+            id: "full_stack".to_string(),
+            name: "Full-Stack".to_string(),
+            description: "Gold-level mastery of two frontend and two backend languages".to_string(),
+            tier: BadgeTier::Gold2,
+            requirements: vec![
+                BadgeRequirement { selector: languages(&frontend), min_tier: BadgeTier::Gold1, min_count: 2 },
+                BadgeRequirement { selector: languages(&backend), min_tier: BadgeTier::Gold1, min_count: 2 },
+            ],
+            image_url: "/badges/special/full_stack.svg".to_string(),
+            animation_url: Some("/badges/special/full_stack_animated.gif".to_string()),
+            enabled: true,
+        },
+        CompositeBadgeRule {
+            id: "completionist".to_string(),
+            name: "Completionist".to_string(),
+            description: "Silver or better in every badge category".to_string(),
+            tier: BadgeTier::Gold1,
+            requirements: CATEGORY_NAMES.iter().map(|name| category(name)).collect(),
+            image_url: "/badges/special/completionist.svg".to_string(),
+            animation_url: Some("/badges/special/completionist_animated.gif".to_string()),
+            enabled: true,
+        },
+    ]
+}
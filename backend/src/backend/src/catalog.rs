@@ -0,0 +1,256 @@
+use crate::badge_rules::{self, BadgeRule, CompositeBadgeRule};
+use crate::models::{BadgeCategory, BadgeTier};
+use crate::rarity::RarityTable;
+use crate::seasons::Season;
+use crate::utils::{LANGUAGE_TIERS, MIN_LANGUAGE_VOLUME};
+use crate::{AI_INNOVATOR_MIN_SCORE, AI_QUALITY_MASTER_MIN_SCORE};
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+// Where a badge definition comes from
+#[derive(Clone, Debug, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum BadgeSource {
+    Rule,      // admin-editable badge rule
+    Language,  // one badge per language, {language} in the id is the canonical language slug
+    Composite, // earned by holding other badges
+    Season,    // only earnable while its season runs
+    AI,        // awarded from the AI analysis, {area} in the id is the canonical expertise slug
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct CatalogTier {
+    pub tier: BadgeTier,
+    pub threshold: Option<u32>,  // None for badges earned at a single fixed tier without a metric
+    pub percentile: Option<u32>, // set when the threshold currently follows the population
+    pub criteria: String,
+    pub holders: u64,            // profiles holding exactly this tier
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BadgeCatalogEntry {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub category: BadgeCategory,
+    pub source: BadgeSource,
+    pub tiers: Vec<CatalogTier>, // lowest first
+    pub image_url: String,
+    pub animation_url: Option<String>,
+    pub holders: u64,    // filled in from the rarity table
+    pub available: bool, // whether the badge can currently be earned
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BadgeCatalog {
+    pub rule_version: u32,
+    pub population: u64,     // profiles holder counts are taken from
+    pub holders_as_of: u64,  // when holder counts were last refreshed, 0 before the first refresh
+    pub entries: Vec<BadgeCatalogEntry>,
+}
+
+/// Every badge definition, earned or not, with holder counts from the latest rarity refresh
+pub fn build_catalog(seasons: &[Season], rarity: &RarityTable, now: u64) -> BadgeCatalog {
+    let rule_set = badge_rules::get_badge_rules();
+    let mut entries = Vec::new();
+
+    entries.push(language_entry());
+    entries.extend(rule_set.rules.iter().map(rule_entry));
+    entries.extend(
+        rule_set.composites.clone().unwrap_or_else(badge_rules::default_composite_rules)
+            .iter()
+            .map(composite_entry),
+    );
+    entries.extend(seasons.iter().map(|season| season_entry(season, now)));
+    entries.extend(ai_entries());
+
+    for entry in entries.iter_mut() {
+        fill_holders(entry, rarity);
+    }
+
+    BadgeCatalog {
+        rule_version: rule_set.version,
+        population: rarity.population,
+        holders_as_of: rarity.computed_at,
+        entries,
+    }
+}
+
+fn rule_entry(rule: &BadgeRule) -> BadgeCatalogEntry {
+    let tiers = rule.tiers.iter()
+        .zip(badge_rules::rule_thresholds(rule))
+        .map(|(threshold, (required, percentile))| CatalogTier {
+            tier: threshold.tier.clone(),
+            threshold: Some(required),
+            percentile,
+            criteria: badge_rules::describe_tier(rule, threshold, required),
+            holders: 0,
+        })
+        .collect();
+
+    BadgeCatalogEntry {
+        id: rule.id.clone(),
+        name: rule.name.clone(),
+        description: rule.description.clone(),
+        category: rule.category.clone(),
+        source: BadgeSource::Rule,
+        tiers,
+        image_url: rule.image_url.clone(),
+        animation_url: rule.animation_url.clone(),
+        holders: 0,
+        available: rule.enabled,
+    }
+}
+
+fn language_entry() -> BadgeCatalogEntry {
+    let tiers = LANGUAGE_TIERS.iter()
+        .map(|(threshold, tier, label)| CatalogTier {
+            tier: tier.clone(),
+            threshold: Some(*threshold),
+            percentile: None,
+            criteria: format!(
+                "{} experience - language score {} (at least {} code, 10 points per doubling of volume, 2 per repository and 2 per recently active repository)",
+                label, threshold, MIN_LANGUAGE_VOLUME
+            ),
+            holders: 0,
+        })
+        .collect();
+
+    BadgeCatalogEntry {
+        id: "lang_{language}".to_string(),
+        name: "{Language} Expert".to_string(),
+        description: "Demonstrated expertise in {Language} programming".to_string(),
+        category: BadgeCategory::Language("{Language}".to_string()),
+        source: BadgeSource::Language,
+        tiers,
+        image_url: "/badges/languages/{language}.svg".to_string(),
+        animation_url: Some("/badges/languages/{language}_animated.gif".to_string()),
+        holders: 0,
+        available: true,
+    }
+}
+
+fn composite_entry(composite: &CompositeBadgeRule) -> BadgeCatalogEntry {
+    let criteria = composite.requirements.iter()
+        .map(badge_rules::describe_requirement)
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    BadgeCatalogEntry {
+        id: composite.id.clone(),
+        name: composite.name.clone(),
+        description: composite.description.clone(),
+        category: BadgeCategory::Special("Composite".to_string()),
+        source: BadgeSource::Composite,
+        tiers: vec![CatalogTier {
+            tier: composite.tier.clone(),
+            threshold: None,
+            percentile: None,
+            criteria,
+            holders: 0,
+        }],
+        image_url: composite.image_url.clone(),
+        animation_url: composite.animation_url.clone(),
+        holders: 0,
+        available: composite.enabled,
+    }
+}
+
+fn season_entry(season: &Season, now: u64) -> BadgeCatalogEntry {
+    let badge_id = season.badge_id();
+    let tiers = season.tiers.iter()
+        .map(|threshold| CatalogTier {
+            tier: threshold.tier.clone(),
+            threshold: Some(threshold.min_value),
+            percentile: None,
+            criteria: format!("{} - {} {} during {}", threshold.label, threshold.min_value, season.metric.describe(), season.name),
+            holders: 0,
+        })
+        .collect();
+
+    BadgeCatalogEntry {
+        id: badge_id.clone(),
+        name: season.name.clone(),
+        description: season.description.clone(),
+        category: BadgeCategory::Special("Season".to_string()),
+        source: BadgeSource::Season,
+        tiers,
+        image_url: season.image_url.clone(),
+        animation_url: season.animation_url.clone(),
+        holders: 0,
+        available: season.is_active(now),
+    }
+}
+
+fn ai_entries() -> Vec<BadgeCatalogEntry> {
+    let entry = |id: &str, name: &str, description: &str, category: BadgeCategory, tier: CatalogTier| BadgeCatalogEntry {
+        id: id.to_string(),
+        name: name.to_string(),
+        description: description.to_string(),
+        category,
+        source: BadgeSource::AI,
+        image_url: format!("/badges/special/{}.svg", id),
+        animation_url: Some(format!("/badges/special/{}_animated.gif", id)),
+        tiers: vec![tier],
+        holders: 0,
+        available: true,
+    };
+    let tier = |tier: BadgeTier, threshold: Option<u32>, criteria: String| CatalogTier {
+        tier,
+        threshold,
+        percentile: None,
+        criteria,
+        holders: 0,
+    };
+
+    let mut expert = entry(
+        "ai_expert_{area}",
+        "AI-Verified {Area} Expert",
+        "Expertise in {Area} verified by AI analysis",
+        BadgeCategory::Language("{Area}".to_string()),
+        tier(BadgeTier::Silver3, None, "AI-identified expertise in an area not already covered by a badge".to_string()),
+    );
+    expert.image_url = "/badges/ai_expertise/{area}.svg".to_string();
+    expert.animation_url = None;
+
+    vec![
+        entry(
+            "ai_quality_master",
+            "AI Quality Master",
+            "Code quality verified by AI analysis",
+            BadgeCategory::Special("AI-Verified".to_string()),
+            tier(BadgeTier::Gold3, Some(AI_QUALITY_MASTER_MIN_SCORE as u32),
+                 format!("AI code quality score of at least {}", AI_QUALITY_MASTER_MIN_SCORE)),
+        ),
+        entry(
+            "ai_innovator",
+            "AI-Verified Innovator",
+            "Innovation patterns recognized by AI",
+            BadgeCategory::Special("AI-Verified".to_string()),
+            tier(BadgeTier::Gold2, Some(AI_INNOVATOR_MIN_SCORE as u32),
+                 format!("AI innovation score of at least {}", AI_INNOVATOR_MIN_SCORE)),
+        ),
+        expert,
+    ]
+}
+
+/// Fill in holder counts; templated ids such as "lang_{language}" sum over every matching badge
+fn fill_holders(entry: &mut BadgeCatalogEntry, rarity: &RarityTable) {
+    let pattern = entry.id.clone();
+    let matches = |badge_id: &str| match pattern.split_once('{') {
+        Some((prefix, _)) => badge_id.starts_with(prefix),
+        None => badge_id == pattern,
+    };
+
+    for tier in entry.tiers.iter_mut() {
+        tier.holders = holders(rarity, matches, Some(&tier.tier));
+    }
+    entry.holders = holders(rarity, matches, None);
+}
+
+/// Holders of the badge ids matching `badge_id`, at one tier or across all tiers
+fn holders(rarity: &RarityTable, badge_id: impl Fn(&str) -> bool, tier: Option<&BadgeTier>) -> u64 {
+    rarity.entries.iter()
+        .filter(|e| badge_id(&e.badge_id) && tier.is_none_or(|t| e.tier == *t))
+        .map(|e| e.holders)
+        .sum()
+}
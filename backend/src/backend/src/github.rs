@@ -1,8 +1,7 @@
-use crate::models::{GitHubAnalysis, GitHubData, GitHubOAuthRequest, GitHubOAuthResponse, Repository, HttpRequest, HttpResponse};
-use ic_cdk::api::management_canister::http_request::{
-    http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod, TransformArgs,
-};
+use crate::models::{GitHubAnalysis, GitHubData, GitHubOAuthRequest, GitHubOAuthResponse, Repository};
+use crate::outcall::{self, OutcallError};
 use ic_cdk::api::time;
+use ic_cdk::management_canister::{HttpHeader, HttpMethod, HttpRequestArgs};
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -22,19 +21,19 @@ pub fn set_github_oauth_config(client_id: String, client_secret: String) {
 }
 
 /// Exchange OAuth code for access token
-pub async fn exchange_oauth_code(oauth_request: GitHubOAuthRequest) -> Result<GitHubOAuthResponse, String> {
+pub async fn exchange_oauth_code(oauth_request: GitHubOAuthRequest) -> Result<GitHubOAuthResponse, OutcallError> {
     let client_id = GITHUB_CLIENT_ID.with(|id| id.borrow().clone())
-        .ok_or("GitHub client ID not configured")?;
+        .ok_or_else(|| OutcallError::NotConfigured("GitHub client ID".to_string()))?;
 
     let client_secret = GITHUB_CLIENT_SECRET.with(|secret| secret.borrow().clone())
-        .ok_or("GitHub client secret not configured")?;
+        .ok_or_else(|| OutcallError::NotConfigured("GitHub client secret".to_string()))?;
 
     let body = format!(
         "client_id={}&client_secret={}&code={}&state={}",
         client_id, client_secret, oauth_request.code, oauth_request.state
     );
 
-    let request = CanisterHttpRequestArgument {
+    let request = HttpRequestArgs {
         url: GITHUB_OAUTH_TOKEN_URL.to_string(),
         method: HttpMethod::POST,
         body: Some(body.into_bytes()),
@@ -56,29 +55,17 @@ pub async fn exchange_oauth_code(oauth_request: GitHubOAuthRequest) -> Result<Gi
         ],
     };
 
-    match http_request(request, 25_000_000_000).await {
-        Ok((response,)) => {
-            if response.status == 200u64 {
-                let body_str = String::from_utf8(response.body)
-                    .map_err(|e| format!("Failed to parse response body: {}", e))?;
+    let body_str = outcall::send("github_oauth", request).await.result?.text()?;
 
-                let oauth_response: GitHubOAuthResponse = serde_json::from_str(&body_str)
-                    .map_err(|e| format!("Failed to parse OAuth response: {}", e))?;
-
-                Ok(oauth_response)
-            } else {
-                Err(format!("GitHub OAuth failed with status: {}", response.status))
-            }
-        }
-        Err((r, m)) => Err(format!("HTTP request failed: {:?} - {}", r, m)),
-    }
+    serde_json::from_str(&body_str)
+        .map_err(|e| OutcallError::InvalidResponse(format!("Failed to parse OAuth response: {}", e)))
 }
 
 /// Fetch GitHub user profile data
-pub async fn fetch_github_user(access_token: &str) -> Result<GitHubData, String> {
+pub async fn fetch_github_user(access_token: &str) -> Result<GitHubData, OutcallError> {
     let url = format!("{}/user", GITHUB_API_BASE);
 
-    let request = CanisterHttpRequestArgument {
+    let request = HttpRequestArgs {
         url,
         method: HttpMethod::GET,
         body: None,
@@ -100,23 +87,12 @@ pub async fn fetch_github_user(access_token: &str) -> Result<GitHubData, String>
         ],
     };
 
-    match http_request(request, 25_000_000_000).await {
-        Ok((response,)) => {
-            if response.status == 200u64 {
-                let body_str = String::from_utf8(response.body)
-                    .map_err(|e| format!("Failed to parse response body: {}", e))?;
-
-                parse_github_user(&body_str)
-            } else {
-                Err(format!("GitHub API request failed with status: {}", response.status))
-            }
-        }
-        Err((r, m)) => Err(format!("HTTP request failed: {:?} - {}", r, m)),
-    }
+    let body_str = outcall::send("github_user", request).await.result?.text()?;
+    parse_github_user(&body_str)
 }
 
 /// Fetch user's repositories
-pub async fn fetch_user_repositories(username: &str, access_token: Option<&str>) -> Result<Vec<Repository>, String> {
+pub async fn fetch_user_repositories(username: &str, access_token: Option<&str>) -> Result<Vec<Repository>, OutcallError> {
     let url = format!("{}/users/{}/repos?type=all&sort=updated&per_page=100", GITHUB_API_BASE, username);
 
     let mut headers = vec![
@@ -137,32 +113,21 @@ pub async fn fetch_user_repositories(username: &str, access_token: Option<&str>)
         });
     }
 
-    let request = CanisterHttpRequestArgument {
+    let request = HttpRequestArgs {
         url,
         method: HttpMethod::GET,
         body: None,
-        max_response_bytes: Some(65536), // INCREASED from 32768 to 65536 for large repo lists
+        max_response_bytes: Some(65536), // Starting limit; grows if the repo list is truncated
         transform: None,
         headers,
     };
 
-    match http_request(request, 25_000_000_000).await {
-        Ok((response,)) => {
-            if response.status == 200u64 {
-                let body_str = String::from_utf8(response.body)
-                    .map_err(|e| format!("Failed to parse response body: {}", e))?;
-
-                parse_repositories(&body_str)
-            } else {
-                Err(format!("GitHub API request failed with status: {}", response.status))
-            }
-        }
-        Err((r, m)) => Err(format!("HTTP request failed: {:?} - {}", r, m)),
-    }
+    let body_str = outcall::send("github_repos", request).await.result?.text()?;
+    parse_repositories(&body_str)
 }
 
 /// Fetch user's contribution statistics
-pub async fn fetch_user_stats(username: &str, access_token: Option<&str>) -> Result<(u32, u32, u32), String> {
+pub async fn fetch_user_stats(username: &str, access_token: Option<&str>) -> Result<(u32, u32, u32), OutcallError> {
     // This would typically require GraphQL API or scraping, for now we'll estimate
     // In a real implementation, you'd use GitHub's GraphQL API for contribution data
 
@@ -179,7 +144,7 @@ pub async fn fetch_user_stats(username: &str, access_token: Option<&str>) -> Res
 }
 
 /// Comprehensive GitHub analysis
-pub async fn perform_comprehensive_analysis(username: &str, access_token: Option<&str>) -> Result<GitHubAnalysis, String> {
+pub async fn perform_comprehensive_analysis(username: &str, access_token: Option<&str>) -> Result<GitHubAnalysis, OutcallError> {
     ic_cdk::println!("Starting comprehensive GitHub analysis for: {}", username);
 
     // Fetch user repositories
@@ -226,7 +191,7 @@ pub async fn perform_comprehensive_analysis(username: &str, access_token: Option
 }
 
 /// Validate GitHub username exists
-pub async fn validate_github_username(username: &str) -> Result<bool, String> {
+pub async fn validate_github_username(username: &str) -> Result<bool, OutcallError> {
     if username.is_empty() || username.len() > 39 {
         return Ok(false);
     }
@@ -238,7 +203,7 @@ pub async fn validate_github_username(username: &str) -> Result<bool, String> {
 
     let url = format!("{}/users/{}", GITHUB_API_BASE, username);
 
-    let request = CanisterHttpRequestArgument {
+    let request = HttpRequestArgs {
         url,
         method: HttpMethod::GET,
         body: None,
@@ -252,17 +217,18 @@ pub async fn validate_github_username(username: &str) -> Result<bool, String> {
         ],
     };
 
-    match http_request(request, 25_000_000_000).await {
-        Ok((response,)) => Ok(response.status == 200u64),
-        Err(_) => Ok(false),
+    match outcall::send("github_user_lookup", request).await.result {
+        Ok(response) => Ok(response.status == 200),
+        Err(OutcallError::HttpStatus { status: 404, .. }) => Ok(false),
+        Err(e) => Err(e),
     }
 }
 
 // Helper Functions
 
-fn parse_github_user(json_str: &str) -> Result<GitHubData, String> {
+fn parse_github_user(json_str: &str) -> Result<GitHubData, OutcallError> {
     let user: Value = serde_json::from_str(json_str)
-        .map_err(|e| format!("Failed to parse user JSON: {}", e))?;
+        .map_err(|e| OutcallError::InvalidResponse(format!("Failed to parse user JSON: {}", e)))?;
 
     Ok(GitHubData {
         login: user["login"].as_str().unwrap_or("").to_string(),
@@ -277,12 +243,12 @@ fn parse_github_user(json_str: &str) -> Result<GitHubData, String> {
     })
 }
 
-fn parse_repositories(json_str: &str) -> Result<Vec<Repository>, String> {
+fn parse_repositories(json_str: &str) -> Result<Vec<Repository>, OutcallError> {
     let repos: Value = serde_json::from_str(json_str)
-        .map_err(|e| format!("Failed to parse repositories JSON: {}", e))?;
+        .map_err(|e| OutcallError::InvalidResponse(format!("Failed to parse repositories JSON: {}", e)))?;
 
     let repos_array = repos.as_array()
-        .ok_or("Expected array of repositories")?;
+        .ok_or_else(|| OutcallError::InvalidResponse("Expected array of repositories".to_string()))?;
 
     let mut repositories = Vec::new();
 
@@ -419,18 +385,32 @@ fn estimate_yearly_contributions(repositories: &[Repository]) -> u32 {
     current_year_repos * 20
 }
 
-fn parse_github_date(date_str: &str) -> Option<u64> {
-    // Simple parsing for ISO 8601 dates from GitHub
-    // In production, you'd use a proper date parsing library
-    if date_str.len() >= 4 {
-        if let Ok(year) = date_str[0..4].parse::<u32>() {
-            // Very rough conversion to nanoseconds since epoch
-            let years_since_1970 = year.saturating_sub(1970);
-            let approx_ns = (years_since_1970 as u64) * 365 * 24 * 60 * 60 * 1_000_000_000;
-            return Some(approx_ns);
-        }
+/// Parse a GitHub ISO 8601 timestamp (e.g. "2024-10-15T12:34:56Z") into nanoseconds since the epoch
+pub fn parse_github_date(date_str: &str) -> Option<u64> {
+    let field = |range: std::ops::Range<usize>| date_str.get(range).and_then(|s| s.parse::<u64>().ok());
+
+    let year = field(0..4)?;
+    let month = field(5..7).unwrap_or(1);
+    let day = field(8..10).unwrap_or(1);
+    let hour = field(11..13).unwrap_or(0);
+    let minute = field(14..16).unwrap_or(0);
+    let second = field(17..19).unwrap_or(0);
+
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
     }
-    None
+
+    // Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's days_from_civil)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y / 400;
+    let year_of_era = y - era * 400;
+    let shifted_month = (month + 9) % 12; // March = 0
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146097 + day_of_era).checked_sub(719468)?;
+
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+    Some(seconds * 1_000_000_000)
 }
 
 fn is_from_current_year(date_str: &str) -> bool {
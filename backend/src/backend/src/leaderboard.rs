@@ -0,0 +1,809 @@
+use crate::badge_ids;
+use crate::models::UserProfile;
+use crate::seasons;
+use crate::{
+    Memory, LEADERBOARD_INDEX_MEMORY_ID, LEADERBOARD_SCORES_MEMORY_ID, MEMORY_MANAGER,
+    SCOPED_LEADERBOARD_INDEX_MEMORY_ID, SCOPED_LEADERBOARD_STATE_MEMORY_ID,
+};
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+pub const MAX_PAGE_SIZE: u32 = 500;
+
+// Scores below this are counted exactly by the rank tree; higher scores share its last slot and
+// are ranked by walking the top of the index, which only holds the few users above it
+const RANKED_SCORES: usize = 1 << 16;
+// The same for each scoped leaderboard, smaller since every scope keeps its own tree
+const SCOPED_RANKED_SCORES: usize = 1 << 12;
+
+const NANOS_PER_DAY: u64 = 24 * 3600 * 1_000_000_000;
+const MAX_SCOPE_LENGTH: usize = 128;
+
+// Position in the leaderboard: highest score first, ties broken by principal. Also the page cursor.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct LeaderboardKey {
+    pub score: u64,
+    pub principal: Principal,
+}
+
+impl Ord for LeaderboardKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.score.cmp(&self.score).then_with(|| self.principal.cmp(&other.principal))
+    }
+}
+
+impl PartialOrd for LeaderboardKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Storable for LeaderboardKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = self.score.to_be_bytes().to_vec();
+        bytes.extend_from_slice(self.principal.as_slice());
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let (score, principal) = bytes.split_at(8);
+        Self {
+            score: u64::from_be_bytes(score.try_into().unwrap()),
+            principal: Principal::from_slice(principal),
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Bounded {
+        max_size: 8 + Principal::MAX_LENGTH_IN_BYTES as u32,
+        is_fixed_size: false,
+    };
+}
+
+// What a leaderboard row shows, kept in the index so listing never decodes full profiles
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct LeaderboardSummary {
+    pub username: String,
+    pub avatar_url: Option<String>,
+    pub badge_count: u32,
+}
+
+impl Storable for LeaderboardSummary {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum LeaderboardCategory {
+    Language,
+    Contribution,
+    Achievement,
+    Special,
+}
+
+#[derive(Clone, Debug, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum TimeWindow {
+    Week,           // since Monday 00:00 UTC
+    Month,          // since the 1st of the month, UTC
+    Season(String), // since a running season started, by season id
+}
+
+// A leaderboard narrower than the overall one
+#[derive(Clone, Debug, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum LeaderboardScope {
+    Language(String),              // current reputation from badges in this language, any alias
+    Category(LeaderboardCategory), // current reputation from badges in this category
+    Window(TimeWindow),            // lifetime reputation gained within the window
+}
+
+// Position in a scoped leaderboard; scopes are stored as "language:rust", "week:2912", ...
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ScopedKey {
+    scope: String,
+    score: u64,
+    principal: Principal,
+}
+
+impl Ord for ScopedKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.scope.cmp(&other.scope)
+            .then_with(|| other.score.cmp(&self.score))
+            .then_with(|| self.principal.cmp(&other.principal))
+    }
+}
+
+impl PartialOrd for ScopedKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Storable for ScopedKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = vec![self.scope.len() as u8];
+        bytes.extend_from_slice(self.scope.as_bytes());
+        bytes.extend_from_slice(&self.score.to_be_bytes());
+        bytes.extend_from_slice(self.principal.as_slice());
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let (scope, rest) = bytes[1..].split_at(bytes[0] as usize);
+        let (score, principal) = rest.split_at(8);
+        Self {
+            scope: String::from_utf8(scope.to_vec()).unwrap(),
+            score: u64::from_be_bytes(score.try_into().unwrap()),
+            principal: Principal::from_slice(principal),
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Bounded {
+        max_size: 1 + MAX_SCOPE_LENGTH as u32 + 8 + Principal::MAX_LENGTH_IN_BYTES as u32,
+        is_fixed_size: false,
+    };
+}
+
+// A user's scoped scores, and their lifetime reputation when each running window began
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+struct ScopedState {
+    lifetime: u64, // lifetime reputation at the last update
+    scores: Vec<(String, u64)>,
+    window_baselines: Vec<(String, u64)>,
+}
+
+impl Storable for ScopedState {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub principal: Principal,
+    pub username: String,
+    pub avatar_url: Option<String>,
+    pub score: u64,
+    pub rank: u64, // users with equal scores share a rank
+    pub badge_count: u32,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct LeaderboardPage {
+    pub entries: Vec<LeaderboardEntry>,
+    pub next_cursor: Option<LeaderboardKey>, // pass back to get the following page, None on the last page
+    pub total: u64,
+}
+
+thread_local! {
+    static LEADERBOARD_INDEX: RefCell<StableBTreeMap<LeaderboardKey, LeaderboardSummary, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(LEADERBOARD_INDEX_MEMORY_ID)),
+        )
+    );
+
+    // Score each user is indexed under, to find their old key when the score changes
+    static LEADERBOARD_SCORES: RefCell<StableBTreeMap<Principal, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(LEADERBOARD_SCORES_MEMORY_ID)),
+        )
+    );
+
+    // Fenwick tree of user counts per score, rebuilt from the index after an upgrade
+    static SCORE_COUNTS: RefCell<ScoreCounts> = const { RefCell::new(ScoreCounts::new(RANKED_SCORES)) };
+
+    static SCOPED_INDEX: RefCell<StableBTreeMap<ScopedKey, LeaderboardSummary, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SCOPED_LEADERBOARD_INDEX_MEMORY_ID)),
+        )
+    );
+
+    // Kept across index rebuilds so window gains survive them
+    static SCOPED_STATE: RefCell<StableBTreeMap<Principal, ScopedState, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SCOPED_LEADERBOARD_STATE_MEMORY_ID)),
+        )
+    );
+
+    // Users and rank tree per scope, rebuilt from the scoped state after an upgrade
+    static SCOPE_COUNTS: RefCell<BTreeMap<String, ScopeCounts>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+struct ScoreCounts {
+    slots: usize,
+    tree: Vec<u64>, // 1-based, empty until built
+}
+
+impl ScoreCounts {
+    const fn new(slots: usize) -> Self {
+        Self { slots, tree: Vec::new() }
+    }
+
+    fn slot(&self, score: u64) -> usize {
+        (score as usize).min(self.slots - 1) + 1
+    }
+
+    fn add(&mut self, score: u64, delta: i64) {
+        if self.tree.is_empty() {
+            self.tree = vec![0; self.slots + 1];
+        }
+        let mut i = self.slot(score);
+        while i <= self.slots {
+            self.tree[i] = self.tree[i].saturating_add_signed(delta);
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Users with a score in a slot up to and including the given score's slot
+    fn at_or_below(&self, score: u64) -> u64 {
+        if self.tree.is_empty() {
+            return 0;
+        }
+        let mut i = self.slot(score);
+        let mut count = 0;
+        while i > 0 {
+            count += self.tree[i];
+            i -= i & i.wrapping_neg();
+        }
+        count
+    }
+}
+
+struct ScopeCounts {
+    size: u64,
+    counts: ScoreCounts,
+}
+
+/// Count a user in or out of a scope's size and rank tree
+fn add_scope_count(sizes: &mut BTreeMap<String, ScopeCounts>, scope: &str, score: u64, delta: i64) {
+    let scope_counts = sizes.entry(scope.to_string())
+        .or_insert_with(|| ScopeCounts { size: 0, counts: ScoreCounts::new(SCOPED_RANKED_SCORES) });
+    scope_counts.size = scope_counts.size.saturating_add_signed(delta);
+    scope_counts.counts.add(score, delta);
+    if scope_counts.size == 0 {
+        sizes.remove(scope);
+    }
+}
+
+/// Only GitHub-connected users are ranked
+pub fn is_ranked(profile: &UserProfile) -> bool {
+    profile.github_connected
+}
+
+/// Re-index a user after their profile was stored
+pub fn update_entry(user: Principal, profile: &UserProfile, now: u64) {
+    remove_entry(user);
+    update_scoped_entries(user, profile, now);
+    if !is_ranked(profile) {
+        return;
+    }
+
+    let key = LeaderboardKey { score: profile.reputation_score, principal: user };
+    let summary = LeaderboardSummary {
+        username: profile.github_username.clone(),
+        avatar_url: profile.github_data.as_ref().map(|d| d.avatar_url.clone()),
+        badge_count: profile.total_badges,
+    };
+
+    LEADERBOARD_INDEX.with(|index| index.borrow_mut().insert(key, summary));
+    LEADERBOARD_SCORES.with(|scores| scores.borrow_mut().insert(user, profile.reputation_score));
+    SCORE_COUNTS.with(|counts| counts.borrow_mut().add(profile.reputation_score, 1));
+}
+
+pub fn remove_entry(user: Principal) {
+    let Some(score) = LEADERBOARD_SCORES.with(|scores| scores.borrow_mut().remove(&user)) else {
+        return;
+    };
+    LEADERBOARD_INDEX.with(|index| index.borrow_mut().remove(&LeaderboardKey { score, principal: user }));
+    SCORE_COUNTS.with(|counts| counts.borrow_mut().add(score, -1));
+}
+
+/// Rebuild the indexes from scratch, e.g. when profiles predate them. Window baselines are kept.
+pub fn rebuild_index(profiles: impl Iterator<Item = (Principal, UserProfile)>, now: u64) {
+    LEADERBOARD_INDEX.with(|index| index.borrow_mut().clear_new());
+    LEADERBOARD_SCORES.with(|scores| scores.borrow_mut().clear_new());
+    SCORE_COUNTS.with(|counts| *counts.borrow_mut() = ScoreCounts::new(RANKED_SCORES));
+    SCOPED_INDEX.with(|index| index.borrow_mut().clear_new());
+    SCOPE_COUNTS.with(|counts| counts.borrow_mut().clear());
+
+    let mut seen = Vec::new();
+    for (user, profile) in profiles {
+        update_entry(user, &profile, now);
+        seen.push(user);
+    }
+
+    // Drop the state of profiles that no longer exist
+    let stale: Vec<Principal> = SCOPED_STATE.with(|state| {
+        state.borrow().keys().filter(|user| seen.binary_search(user).is_err()).collect()
+    });
+    SCOPED_STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        for user in stale {
+            state.remove(&user);
+        }
+    });
+}
+
+/// Rebuild the in-memory rank tree and scope sizes from the stable indexes after an upgrade
+pub fn rebuild_rank_counts() {
+    let mut counts = ScoreCounts::new(RANKED_SCORES);
+    LEADERBOARD_SCORES.with(|scores| {
+        for (_, score) in scores.borrow().iter() {
+            counts.add(score, 1);
+        }
+    });
+    SCORE_COUNTS.with(|c| *c.borrow_mut() = counts);
+
+    let mut sizes = BTreeMap::new();
+    SCOPED_STATE.with(|state| {
+        for (_, user_state) in state.borrow().iter() {
+            for (scope, score) in user_state.scores {
+                add_scope_count(&mut sizes, &scope, score, 1);
+            }
+        }
+    });
+    SCOPE_COUNTS.with(|s| *s.borrow_mut() = sizes);
+}
+
+pub fn index_len() -> u64 {
+    LEADERBOARD_SCORES.with(|scores| scores.borrow().len())
+}
+
+/// Whether the indexes are missing ranked users, e.g. after upgrading from a version without them
+pub fn needs_backfill() -> bool {
+    index_len() == 0 || SCOPED_STATE.with(|state| state.borrow().len()) < index_len()
+}
+
+/// 1 + the number of users with a strictly higher score
+fn rank_for_score(score: u64) -> u64 {
+    let total = index_len();
+    if (score as usize) < RANKED_SCORES - 1 {
+        return 1 + total - SCORE_COUNTS.with(|counts| counts.borrow().at_or_below(score));
+    }
+
+    // Top slot: count the users above this score directly
+    LEADERBOARD_INDEX.with(|index| {
+        1 + index.borrow().keys().take_while(|key| key.score > score).count() as u64
+    })
+}
+
+pub fn get_page(cursor: Option<LeaderboardKey>, limit: Option<u32>) -> LeaderboardPage {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE) as usize;
+    let start = cursor.map_or(Bound::Unbounded, Bound::Excluded);
+
+    let rows: Vec<(LeaderboardKey, LeaderboardSummary)> = LEADERBOARD_INDEX.with(|index| {
+        index.borrow().range((start, Bound::Unbounded)).take(limit + 1).collect()
+    });
+
+    let has_more = rows.len() > limit;
+    let mut entries = Vec::with_capacity(limit);
+    let mut last_rank: Option<(u64, u64)> = None; // (score, rank) of the previous row
+    for (key, summary) in rows.into_iter().take(limit) {
+        let rank = match last_rank {
+            Some((score, rank)) if score == key.score => rank,
+            _ => rank_for_score(key.score),
+        };
+        last_rank = Some((key.score, rank));
+        entries.push(entry(key, summary, rank));
+    }
+
+    let next_cursor = if has_more {
+        entries.last().map(|e| LeaderboardKey { score: e.score, principal: e.principal })
+    } else {
+        None
+    };
+
+    LeaderboardPage { entries, next_cursor, total: index_len() }
+}
+
+/// A user's leaderboard row, None when they are not ranked
+pub fn get_entry(user: Principal) -> Option<LeaderboardEntry> {
+    let score = LEADERBOARD_SCORES.with(|scores| scores.borrow().get(&user))?;
+    let key = LeaderboardKey { score, principal: user };
+    let summary = LEADERBOARD_INDEX.with(|index| index.borrow().get(&key))?;
+    Some(entry(key, summary, rank_for_score(score)))
+}
+
+fn entry(key: LeaderboardKey, summary: LeaderboardSummary, rank: u64) -> LeaderboardEntry {
+    LeaderboardEntry {
+        principal: key.principal,
+        username: summary.username,
+        avatar_url: summary.avatar_url,
+        score: key.score,
+        rank,
+        badge_count: summary.badge_count,
+    }
+}
+
+/// Storage key of a scope, checking that the language is named and the season is running
+fn scope_key(scope: &LeaderboardScope, now: u64) -> Result<String, String> {
+    let key = match scope {
+        LeaderboardScope::Language(language) => {
+            let slug = badge_ids::canonical_slug(language);
+            if slug.is_empty() {
+                return Err("Language must not be empty".to_string());
+            }
+            format!("language:{}", slug)
+        }
+        LeaderboardScope::Category(category) => category_scope(category),
+        LeaderboardScope::Window(TimeWindow::Week) => week_scope(now),
+        LeaderboardScope::Window(TimeWindow::Month) => month_scope(now),
+        LeaderboardScope::Window(TimeWindow::Season(id)) => {
+            if !seasons::get_seasons().iter().any(|s| s.id == *id && s.is_active(now)) {
+                return Err(format!("Season '{}' is not running", id));
+            }
+            format!("season:{}", id)
+        }
+    };
+    Ok(key)
+}
+
+fn category_scope(category: &LeaderboardCategory) -> String {
+    format!("category:{:?}", category).to_lowercase()
+}
+
+/// Weeks start on Monday; 1970-01-01 was a Thursday
+fn week_scope(now: u64) -> String {
+    format!("week:{}", (now / NANOS_PER_DAY + 3) / 7)
+}
+
+fn month_scope(now: u64) -> String {
+    // Civil date from days since 1970-01-01 (Howard Hinnant's civil_from_days)
+    let z = now / NANOS_PER_DAY + 719468;
+    let era = z / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153; // March = 0
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("month:{}-{:02}", year, month)
+}
+
+/// Scopes of the windows running now
+fn current_windows(now: u64) -> Vec<String> {
+    let mut windows = vec![week_scope(now), month_scope(now)];
+    windows.extend(
+        seasons::get_seasons().iter()
+            .filter(|season| season.is_active(now))
+            .map(|season| format!("season:{}", season.id)),
+    );
+    windows
+}
+
+fn is_window(scope: &str) -> bool {
+    ["week:", "month:", "season:"].iter().any(|prefix| scope.starts_with(prefix))
+}
+
+/// A user's score in every scope they place in
+fn scoped_scores(profile: &UserProfile, lifetime: u64, baselines: &[(String, u64)]) -> Vec<(String, u64)> {
+    let mut scores = Vec::new();
+    if let Some(breakdown) = &profile.reputation_breakdown {
+        scores.extend(breakdown.languages.iter().map(|l| (format!("language:{}", l.language), l.score)));
+        scores.extend([
+            (LeaderboardCategory::Language, breakdown.language),
+            (LeaderboardCategory::Contribution, breakdown.contribution),
+            (LeaderboardCategory::Achievement, breakdown.achievement),
+            (LeaderboardCategory::Special, breakdown.special),
+        ].iter().map(|(category, score)| (category_scope(category), *score)));
+    }
+    scores.extend(baselines.iter().map(|(window, baseline)| (window.clone(), lifetime.saturating_sub(*baseline))));
+
+    scores.retain(|(scope, score)| *score > 0 && scope.len() <= MAX_SCOPE_LENGTH);
+    scores
+}
+
+fn update_scoped_entries(user: Principal, profile: &UserProfile, now: u64) {
+    let previous = SCOPED_STATE.with(|state| state.borrow_mut().remove(&user));
+    if let Some(previous) = &previous {
+        remove_scoped(user, &previous.scores);
+    }
+    if !is_ranked(profile) {
+        return;
+    }
+
+    // A window's baseline is the lifetime reputation before the user's first update within it
+    let lifetime = profile.lifetime_reputation.unwrap_or(profile.reputation_score);
+    let window_baselines: Vec<(String, u64)> = current_windows(now).into_iter()
+        .map(|window| {
+            let baseline = match &previous {
+                Some(p) => p.window_baselines.iter().find(|(w, _)| *w == window).map_or(p.lifetime, |(_, b)| *b),
+                None => lifetime,
+            };
+            (window, baseline)
+        })
+        .collect();
+
+    let state = ScopedState {
+        lifetime,
+        scores: scoped_scores(profile, lifetime, &window_baselines),
+        window_baselines,
+    };
+    let summary = LeaderboardSummary {
+        username: profile.github_username.clone(),
+        avatar_url: profile.github_data.as_ref().map(|d| d.avatar_url.clone()),
+        badge_count: profile.total_badges,
+    };
+
+    SCOPED_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for (scope, score) in &state.scores {
+            index.insert(ScopedKey { scope: scope.clone(), score: *score, principal: user }, summary.clone());
+        }
+    });
+    SCOPE_COUNTS.with(|sizes| {
+        let mut sizes = sizes.borrow_mut();
+        for (scope, score) in &state.scores {
+            add_scope_count(&mut sizes, scope, *score, 1);
+        }
+    });
+    SCOPED_STATE.with(|s| s.borrow_mut().insert(user, state));
+}
+
+fn remove_scoped(user: Principal, scores: &[(String, u64)]) {
+    SCOPED_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for (scope, score) in scores {
+            index.remove(&ScopedKey { scope: scope.clone(), score: *score, principal: user });
+        }
+    });
+    SCOPE_COUNTS.with(|sizes| {
+        let mut sizes = sizes.borrow_mut();
+        for (scope, score) in scores {
+            add_scope_count(&mut sizes, scope, *score, -1);
+        }
+    });
+}
+
+/// Drop window leaderboards that have ended, for users not updated since. Returns the entries removed.
+pub fn prune_windows(now: u64) -> u64 {
+    let windows = current_windows(now);
+    let expired = |scope: &str| is_window(scope) && !windows.iter().any(|w| w == scope);
+
+    let stale: Vec<(Principal, ScopedState)> = SCOPED_STATE.with(|state| {
+        state.borrow().iter()
+            .filter(|(_, s)| s.scores.iter().any(|(scope, _)| expired(scope)))
+            .collect()
+    });
+
+    let mut removed = 0;
+    for (user, mut state) in stale {
+        let (ended, kept): (Vec<_>, Vec<_>) = state.scores.into_iter().partition(|(scope, _)| expired(scope));
+        removed += ended.len() as u64;
+        remove_scoped(user, &ended);
+        state.scores = kept;
+        state.window_baselines.retain(|(window, _)| !expired(window));
+        SCOPED_STATE.with(|s| s.borrow_mut().insert(user, state));
+    }
+    removed
+}
+
+/// Smallest key of a scope: the highest possible score, then the smallest principal
+fn scope_start(scope: &str) -> ScopedKey {
+    ScopedKey { scope: scope.to_string(), score: u64::MAX, principal: Principal::from_slice(&[]) }
+}
+
+fn scope_size(scope: &str) -> u64 {
+    SCOPE_COUNTS.with(|counts| counts.borrow().get(scope).map_or(0, |c| c.size))
+}
+
+/// 1 + the number of users in the scope with a strictly higher score
+fn scoped_rank(scope: &str, score: u64) -> u64 {
+    if (score as usize) < SCOPED_RANKED_SCORES - 1 {
+        let at_or_below = SCOPE_COUNTS.with(|counts| {
+            counts.borrow().get(scope).map_or(0, |c| c.counts.at_or_below(score))
+        });
+        return 1 + scope_size(scope).saturating_sub(at_or_below);
+    }
+
+    // Top slot: count the users above this score directly
+    SCOPED_INDEX.with(|index| {
+        1 + index.borrow().range(scope_start(scope)..)
+            .take_while(|(key, _)| key.scope == scope && key.score > score)
+            .count() as u64
+    })
+}
+
+pub fn get_scoped_page(scope: &LeaderboardScope, cursor: Option<LeaderboardKey>, limit: Option<u32>, now: u64) -> Result<LeaderboardPage, String> {
+    let scope = scope_key(scope, now)?;
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE) as usize;
+    let start = match cursor {
+        Some(cursor) => Bound::Excluded(ScopedKey { scope: scope.clone(), score: cursor.score, principal: cursor.principal }),
+        None => Bound::Included(scope_start(&scope)),
+    };
+
+    let rows: Vec<(ScopedKey, LeaderboardSummary)> = SCOPED_INDEX.with(|index| {
+        index.borrow().range((start, Bound::Unbounded))
+            .take_while(|(key, _)| key.scope == scope)
+            .take(limit + 1)
+            .collect()
+    });
+
+    let has_more = rows.len() > limit;
+    let mut entries: Vec<LeaderboardEntry> = Vec::with_capacity(limit);
+    let mut last_rank: Option<(u64, u64)> = None; // (score, rank) of the previous row
+    for (key, summary) in rows.into_iter().take(limit) {
+        let rank = match last_rank {
+            Some((score, rank)) if score == key.score => rank,
+            _ => scoped_rank(&scope, key.score),
+        };
+        last_rank = Some((key.score, rank));
+        entries.push(entry(LeaderboardKey { score: key.score, principal: key.principal }, summary, rank));
+    }
+
+    let next_cursor = if has_more {
+        entries.last().map(|e| LeaderboardKey { score: e.score, principal: e.principal })
+    } else {
+        None
+    };
+    let total = scope_size(&scope);
+
+    Ok(LeaderboardPage { entries, next_cursor, total })
+}
+
+/// A user's row in a scoped leaderboard, None when they do not place in it
+pub fn get_scoped_entry(scope: &LeaderboardScope, user: Principal, now: u64) -> Result<Option<LeaderboardEntry>, String> {
+    let scope = scope_key(scope, now)?;
+    let Some(score) = SCOPED_STATE.with(|state| state.borrow().get(&user))
+        .and_then(|state| state.scores.into_iter().find(|(s, _)| *s == scope).map(|(_, score)| score))
+    else {
+        return Ok(None);
+    };
+
+    let rank = scoped_rank(&scope, score);
+    let key = ScopedKey { scope, score, principal: user };
+    let Some(summary) = SCOPED_INDEX.with(|index| index.borrow().get(&key)) else {
+        return Ok(None);
+    };
+    Ok(Some(entry(LeaderboardKey { score, principal: user }, summary, rank)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::parse_github_date;
+
+    fn user(id: u8) -> Principal {
+        Principal::from_slice(&[id])
+    }
+
+    fn profile(id: u8, score: u64) -> UserProfile {
+        UserProfile {
+            user_principal: user(id),
+            github_username: format!("user{}", id),
+            github_connected: true,
+            github_data: None,
+            created_at: 0,
+            updated_at: 0,
+            last_github_sync: None,
+            reputation_score: score,
+            badges: Vec::new(),
+            total_badges: 0,
+            revoked_badges: None,
+            lifetime_reputation: None,
+            current_reputation: None,
+            reputation_breakdown: None,
+        }
+    }
+
+    fn rank(id: u8) -> Option<u64> {
+        get_entry(user(id)).map(|entry| entry.rank)
+    }
+
+    fn date(date: &str) -> u64 {
+        parse_github_date(date).unwrap()
+    }
+
+    #[test]
+    fn score_counts_track_inserts_removals_and_duplicates() {
+        let mut counts = ScoreCounts::new(8);
+        assert_eq!(counts.at_or_below(7), 0);
+
+        counts.add(3, 1);
+        counts.add(3, 1);
+        counts.add(5, 1);
+        assert_eq!(counts.at_or_below(2), 0);
+        assert_eq!(counts.at_or_below(3), 2);
+        assert_eq!(counts.at_or_below(4), 2);
+        assert_eq!(counts.at_or_below(5), 3);
+
+        counts.add(3, -1);
+        assert_eq!(counts.at_or_below(3), 1);
+        assert_eq!(counts.at_or_below(5), 2);
+
+        // Scores past the range share the last slot
+        counts.add(100, 1);
+        assert_eq!(counts.at_or_below(6), 2);
+        assert_eq!(counts.at_or_below(7), 3);
+        assert_eq!(counts.at_or_below(1_000), 3);
+    }
+
+    #[test]
+    fn ranks_count_users_with_a_strictly_higher_score() {
+        for (id, score) in [(1, 10), (2, 10), (3, 20), (4, 5)] {
+            update_entry(user(id), &profile(id, score), 0);
+        }
+        assert_eq!(index_len(), 4);
+        assert_eq!(rank(3), Some(1));
+        assert_eq!(rank(1), Some(2));
+        assert_eq!(rank(2), Some(2));
+        assert_eq!(rank(4), Some(4));
+
+        remove_entry(user(3));
+        assert_eq!(rank(3), None);
+        assert_eq!(rank(1), Some(1));
+        assert_eq!(rank(4), Some(3));
+
+        // A changed score replaces the user's old entry
+        update_entry(user(2), &profile(2, 30), 0);
+        assert_eq!(index_len(), 3);
+        assert_eq!(rank(2), Some(1));
+        assert_eq!(rank(1), Some(2));
+
+        let page = get_page(None, Some(10));
+        let ranks: Vec<u64> = page.entries.iter().map(|e| e.rank).collect();
+        assert_eq!(ranks, vec![1, 2, 3]);
+        assert_eq!(page.total, 3);
+    }
+
+    #[test]
+    fn scores_past_the_rank_tree_are_ranked_by_walking_the_index() {
+        let top = RANKED_SCORES as u64;
+        for (id, score) in [(1, top + 10), (2, top + 5), (3, top + 5), (4, top - 1), (5, 100)] {
+            update_entry(user(id), &profile(id, score), 0);
+        }
+        assert_eq!(rank(1), Some(1));
+        assert_eq!(rank(2), Some(2));
+        assert_eq!(rank(3), Some(2));
+        assert_eq!(rank(4), Some(4));
+        assert_eq!(rank(5), Some(5));
+    }
+
+    #[test]
+    fn github_dates_convert_to_nanoseconds_since_the_epoch() {
+        assert_eq!(parse_github_date("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_github_date("2000-03-01T00:00:00Z"), Some(951_868_800 * 1_000_000_000));
+        assert_eq!(parse_github_date("2024-02-29T12:34:56Z"), Some(1_709_210_096 * 1_000_000_000));
+        assert_eq!(parse_github_date("2023-12-31T23:59:59Z"), Some(1_704_067_199 * 1_000_000_000));
+        assert_eq!(parse_github_date("1969-12-31T23:59:59Z"), None);
+        assert_eq!(parse_github_date("2024-13-01T00:00:00Z"), None);
+        assert_eq!(parse_github_date("not a date"), None);
+    }
+
+    #[test]
+    fn month_windows_follow_the_calendar() {
+        assert_eq!(month_scope(0), "month:1970-01");
+        assert_eq!(month_scope(date("2023-12-31T23:59:59Z")), "month:2023-12");
+        assert_eq!(month_scope(date("2024-01-01T00:00:00Z")), "month:2024-01");
+        assert_eq!(month_scope(date("2024-02-29T23:59:59Z")), "month:2024-02");
+        assert_eq!(month_scope(date("2024-03-01T00:00:00Z")), "month:2024-03");
+        assert_eq!(month_scope(date("2000-02-29T12:00:00Z")), "month:2000-02");
+    }
+
+    #[test]
+    fn week_windows_start_on_monday() {
+        // 1970-01-01 was a Thursday
+        assert_eq!(week_scope(date("1970-01-04T23:59:59Z")), "week:0");
+        assert_eq!(week_scope(date("1970-01-05T00:00:00Z")), "week:1");
+        assert_eq!(week_scope(date("2024-03-03T23:59:59Z")), week_scope(date("2024-02-26T00:00:00Z")));
+        assert_ne!(week_scope(date("2024-03-04T00:00:00Z")), week_scope(date("2024-03-03T23:59:59Z")));
+    }
+}
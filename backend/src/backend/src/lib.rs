@@ -3,25 +3,71 @@ use ic_cdk::{init, post_upgrade, pre_upgrade, query, update};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap};
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
-
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
+use std::ops::Bound;
+use std::time::Duration;
+
+mod badge_ids;
+mod badge_migration;
+mod badge_rules;
+mod catalog;
 mod github;
+mod leaderboard;
 mod llm;
 mod models;
+mod outcall;
+mod percentiles;
+mod rarity;
+mod reputation;
+mod seasons;
 mod utils;
 
 use models::*;
 use utils::*;
 
-type Memory = VirtualMemory<DefaultMemoryImpl>;
+pub(crate) type Memory = VirtualMemory<DefaultMemoryImpl>;
 type ProfileStore = StableBTreeMap<Principal, UserProfile, Memory>;
 type AnalysisStore = StableBTreeMap<String, GitHubAnalysis, Memory>;
+type InsightsStore = StableBTreeMap<Principal, AIInsights, Memory>;
+type SnapshotStore = StableBTreeMap<String, GitHubAnalysis, Memory>;
 
 const PROFILES_MEMORY_ID: MemoryId = MemoryId::new(0);
 const ANALYSIS_MEMORY_ID: MemoryId = MemoryId::new(1);
+pub(crate) const LLM_CACHE_MEMORY_ID: MemoryId = MemoryId::new(2);
+pub(crate) const LLM_BUDGET_MEMORY_ID: MemoryId = MemoryId::new(3);
+pub(crate) const LLM_USAGE_MEMORY_ID: MemoryId = MemoryId::new(4);
+pub(crate) const LLM_CONSENSUS_MEMORY_ID: MemoryId = MemoryId::new(5);
+const INSIGHTS_MEMORY_ID: MemoryId = MemoryId::new(6);
+pub(crate) const OUTCALL_POLICY_MEMORY_ID: MemoryId = MemoryId::new(7);
+pub(crate) const BADGE_RULES_MEMORY_ID: MemoryId = MemoryId::new(8);
+pub(crate) const BADGE_POLICY_MEMORY_ID: MemoryId = MemoryId::new(9);
+pub(crate) const SEASONS_MEMORY_ID: MemoryId = MemoryId::new(10);
+const SNAPSHOTS_MEMORY_ID: MemoryId = MemoryId::new(11);
+pub(crate) const REPUTATION_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(12);
+pub(crate) const LEADERBOARD_INDEX_MEMORY_ID: MemoryId = MemoryId::new(13);
+pub(crate) const LEADERBOARD_SCORES_MEMORY_ID: MemoryId = MemoryId::new(14);
+pub(crate) const SCOPED_LEADERBOARD_INDEX_MEMORY_ID: MemoryId = MemoryId::new(15);
+pub(crate) const SCOPED_LEADERBOARD_STATE_MEMORY_ID: MemoryId = MemoryId::new(16);
+
+// Minimum time between user-triggered syncs or insight regenerations
+const SYNC_INTERVAL_NS: u64 = 3600_000_000_000; // 1 hour in nanoseconds
+
+const RARITY_REFRESH_INTERVAL: Duration = Duration::from_secs(6 * 3600);
+const MIGRATION_BATCH_DELAY: Duration = Duration::from_secs(1);
+const REPUTATION_REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+const PERCENTILE_REFRESH_INTERVAL: Duration = Duration::from_secs(6 * 3600);
+const DISTRIBUTION_REFRESH_INTERVAL: Duration = Duration::from_secs(3600);
+const LLM_USAGE_PRUNE_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+const REFRESH_BATCH_SIZE: usize = 100;
+const REFRESH_BATCH_DELAY: Duration = Duration::from_secs(1);
+
+// Minimum AI scores for the AI-verified special badges
+pub(crate) const AI_QUALITY_MASTER_MIN_SCORE: f32 = 85.0;
+pub(crate) const AI_INNOVATOR_MIN_SCORE: f32 = 80.0;
 
 thread_local! {
-    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+    pub(crate) static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
         RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
 
     static USER_PROFILES: RefCell<ProfileStore> = RefCell::new(
@@ -36,8 +82,35 @@ thread_local! {
         )
     );
 
+    static AI_INSIGHTS: RefCell<InsightsStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(INSIGHTS_MEMORY_ID)),
+        )
+    );
+
+    // Analyses referenced by badge evidence, keyed by their hash
+    static ANALYSIS_SNAPSHOTS: RefCell<SnapshotStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SNAPSHOTS_MEMORY_ID)),
+        )
+    );
+
     static AUTH_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
     static NFT_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+
+    // Set while a batched rarity refresh is working through the profiles
+    static RARITY_REFRESH_RUNNING: Cell<bool> = const { Cell::new(false) };
+    // Set while a batched reputation refresh is working through the profiles
+    static REPUTATION_REFRESH_RUNNING: Cell<bool> = const { Cell::new(false) };
+    // Set when a reputation refresh was asked for while one was running, e.g. by a config change
+    static REPUTATION_RERUN_REQUESTED: Cell<bool> = const { Cell::new(false) };
+    // Set while a batched percentile snapshot is being counted
+    static PERCENTILE_REFRESH_RUNNING: Cell<bool> = const { Cell::new(false) };
+    // Batched metric distribution refresh: whether one is running, whether another was asked
+    // for meanwhile, and what to run once the distributions are current
+    static DISTRIBUTION_REFRESH_RUNNING: Cell<bool> = const { Cell::new(false) };
+    static DISTRIBUTION_RERUN_REQUESTED: Cell<bool> = const { Cell::new(false) };
+    static DISTRIBUTION_WAITERS: RefCell<Vec<fn()>> = const { RefCell::new(Vec::new()) };
 }
 
 #[init]
@@ -67,6 +140,356 @@ fn init(
     } else {
         ic_cdk::println!("VeriFlair Backend initialized with GitHub integration only!");
     }
+
+    start_background_timers();
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    // Rank counts are kept on the heap; the index they are derived from is stable
+    leaderboard::rebuild_rank_counts();
+    // Timers do not survive upgrades
+    start_background_timers();
+}
+
+fn start_background_timers() {
+    ic_cdk_timers::set_timer(Duration::ZERO, backfill_leaderboard);
+    ic_cdk_timers::set_timer(Duration::ZERO, refresh_badge_rarity);
+    ic_cdk_timers::set_timer_interval(RARITY_REFRESH_INTERVAL, refresh_badge_rarity);
+    ic_cdk_timers::set_timer_interval(REPUTATION_REFRESH_INTERVAL, refresh_reputation);
+    ic_cdk_timers::set_timer(Duration::ZERO, refresh_percentiles);
+    ic_cdk_timers::set_timer_interval(PERCENTILE_REFRESH_INTERVAL, refresh_percentiles);
+    ic_cdk_timers::set_timer(Duration::ZERO, scheduled_distribution_refresh);
+    ic_cdk_timers::set_timer_interval(DISTRIBUTION_REFRESH_INTERVAL, scheduled_distribution_refresh);
+    ic_cdk_timers::set_timer_interval(LLM_USAGE_PRUNE_INTERVAL, prune_llm_usage);
+}
+
+/// Persist a profile and keep the leaderboard index in step with it
+fn store_profile(user: Principal, profile: &UserProfile) {
+    USER_PROFILES.with(|profiles| {
+        profiles.borrow_mut().insert(user, profile.clone());
+    });
+    leaderboard::update_entry(user, profile, ic_cdk::api::time());
+}
+
+/// Index profiles stored before the leaderboard index existed
+fn backfill_leaderboard() {
+    if !leaderboard::needs_backfill() {
+        return;
+    }
+    rebuild_leaderboard();
+}
+
+fn rebuild_leaderboard() {
+    let now = ic_cdk::api::time();
+    USER_PROFILES.with(|profiles| leaderboard::rebuild_index(profiles.borrow().iter(), now));
+    ic_cdk::println!("Leaderboard index rebuilt with {} ranked profiles", leaderboard::index_len());
+}
+
+/// Recompute lifetime and time-decayed reputation for every profile
+fn refresh_reputation() {
+    if REPUTATION_REFRESH_RUNNING.with(|running| running.replace(true)) {
+        // Profiles the running pass already covered may have used outdated settings
+        REPUTATION_RERUN_REQUESTED.with(|rerun| rerun.set(true));
+        return;
+    }
+    run_reputation_batch(None, ic_cdk::api::time(), 0);
+}
+
+/// Recompute reputation for the next batch of profiles after `cursor` as of the pass's start
+/// time `now`, then schedule the following batch
+fn run_reputation_batch(cursor: Option<Principal>, now: u64, updated_so_far: usize) {
+    let config = reputation::get_reputation_config();
+    let batch = profile_batch(cursor);
+
+    let mut updated_count = updated_so_far;
+    for (user, mut profile) in batch.iter().cloned() {
+        if reputation::apply_reputation(&mut profile, &config, now) {
+            store_profile(user, &profile);
+            updated_count += 1;
+        }
+    }
+
+    match batch.last() {
+        Some((last, _)) if batch.len() == REFRESH_BATCH_SIZE => {
+            let last = *last;
+            ic_cdk_timers::set_timer(REFRESH_BATCH_DELAY, move || run_reputation_batch(Some(last), now, updated_count));
+        }
+        _ => {
+            REPUTATION_REFRESH_RUNNING.with(|running| running.set(false));
+            let pruned = leaderboard::prune_windows(now);
+            ic_cdk::println!("Reputation refreshed, {} profiles updated, {} ended window entries pruned", updated_count, pruned);
+
+            if REPUTATION_RERUN_REQUESTED.with(|rerun| rerun.replace(false)) {
+                ic_cdk_timers::set_timer(Duration::ZERO, refresh_reputation);
+            }
+        }
+    }
+}
+
+/// Snapshot score distributions so percentile queries do not scan every profile
+fn refresh_percentiles() {
+    if PERCENTILE_REFRESH_RUNNING.with(|running| running.replace(true)) {
+        return;
+    }
+    count_percentile_batch(None, ic_cdk::api::time(), percentiles::PercentileCounts::default());
+}
+
+/// Count the scores of the next batch of profiles after `cursor` as of the pass's start time
+/// `now`, and publish the snapshot once every profile is counted
+fn count_percentile_batch(cursor: Option<Principal>, now: u64, mut counts: percentiles::PercentileCounts) {
+    let batch = profile_batch(cursor);
+    let full = batch.len() == REFRESH_BATCH_SIZE;
+    let last = batch.last().map(|(user, _)| *user);
+    counts.add_profiles(batch.into_iter().map(|(_, profile)| profile), now);
+
+    match last {
+        Some(last) if full => {
+            ic_cdk_timers::set_timer(REFRESH_BATCH_DELAY, move || count_percentile_batch(Some(last), now, counts));
+        }
+        _ => {
+            let snapshot = percentiles::compute_snapshot(counts, now);
+            let groups = snapshot.group_count();
+            percentiles::set_snapshot(snapshot);
+            PERCENTILE_REFRESH_RUNNING.with(|running| running.set(false));
+            ic_cdk::println!("Percentile snapshot refreshed, {} groups", groups);
+        }
+    }
+}
+
+/// Recompute badge rarity across all profiles and write changed scores into badge metadata
+fn refresh_badge_rarity() {
+    if RARITY_REFRESH_RUNNING.with(|running| running.replace(true)) {
+        return;
+    }
+    count_rarity_batch(None, rarity::RarityCounts::default());
+}
+
+/// Count badge holders in the next batch of profiles after `cursor`. Once every profile is
+/// counted, the new rarity table replaces the old one and is written into the profiles.
+fn count_rarity_batch(cursor: Option<Principal>, mut counts: rarity::RarityCounts) {
+    let batch = profile_batch(cursor);
+    let full = batch.len() == REFRESH_BATCH_SIZE;
+    let last = batch.last().map(|(user, _)| *user);
+    counts.add_profiles(batch.into_iter().map(|(_, profile)| profile));
+
+    match last {
+        Some(last) if full => {
+            ic_cdk_timers::set_timer(REFRESH_BATCH_DELAY, move || count_rarity_batch(Some(last), counts));
+        }
+        _ => {
+            ic_cdk::println!("Badge rarity recomputed over {} profiles", counts.population());
+            rarity::set_rarity_table(rarity::compute_rarity(counts, ic_cdk::api::time()));
+            ic_cdk_timers::set_timer(REFRESH_BATCH_DELAY, || run_rarity_batch(None, 0));
+        }
+    }
+}
+
+/// Write the current rarity table into the next batch of profiles after `cursor`, then schedule
+/// the following batch
+fn run_rarity_batch(cursor: Option<Principal>, updated_so_far: usize) {
+    let batch = profile_batch(cursor);
+
+    let mut updated_count = updated_so_far;
+    for (user, mut profile) in batch.iter().cloned() {
+        let (renamed, merged) = canonicalize_badge_ids(&mut profile.badges);
+        if !(apply_rarity(&mut profile.badges) || renamed) {
+            continue;
+        }
+        store_profile(user, &profile);
+        updated_count += 1;
+
+        if !merged.relinked.is_empty() || !merged.orphaned.is_empty() {
+            let update = BadgeUpdate { merged, ..BadgeUpdate::default() };
+            ic_cdk::futures::spawn(async move {
+                sync_badge_nfts(user, profile, &update).await;
+            });
+        }
+    }
+
+    match batch.last() {
+        Some((last, _)) if batch.len() == REFRESH_BATCH_SIZE => {
+            let last = *last;
+            ic_cdk_timers::set_timer(REFRESH_BATCH_DELAY, move || run_rarity_batch(Some(last), updated_count));
+        }
+        _ => {
+            RARITY_REFRESH_RUNNING.with(|running| running.set(false));
+            ic_cdk::println!("Badge rarity refresh finished, {} profiles updated", updated_count);
+        }
+    }
+}
+
+/// Up to `REFRESH_BATCH_SIZE` profiles following `cursor` in key order
+fn profile_batch(cursor: Option<Principal>) -> Vec<(Principal, UserProfile)> {
+    USER_PROFILES.with(|profiles| {
+        let start = cursor.map_or(Bound::Unbounded, Bound::Excluded);
+        profiles.borrow().range((start, Bound::Unbounded)).take(REFRESH_BATCH_SIZE).collect()
+    })
+}
+
+/// Write current rarity scores into badge metadata, returning whether anything changed
+fn apply_rarity(badges: &mut [Badge]) -> bool {
+    let mut changed = false;
+    for badge in badges.iter_mut() {
+        if let Some(score) = rarity::rarity_score(&badge.id, &badge.tier) {
+            if badge.metadata.rarity_score != score {
+                badge.metadata.rarity_score = score;
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+fn prune_llm_usage() {
+    let removed = llm::prune_llm_usage();
+    ic_cdk::println!("Pruned {} stale LLM usage entries", removed);
+}
+
+/// Periodic distribution refresh; skipped while a migration runs so all its batches are
+/// evaluated against the distributions it started with
+fn scheduled_distribution_refresh() {
+    if badge_migration::running_migration().is_none() {
+        refresh_metric_distributions(None);
+    }
+}
+
+/// Recompute percentile distributions for the active rules and any candidate being previewed,
+/// in batches of cached analyses, then call `on_done`. A request made while a pass is running
+/// starts another pass once it ends, so the new pass covers the current rules.
+fn refresh_metric_distributions(on_done: Option<fn()>) {
+    if let Some(on_done) = on_done {
+        DISTRIBUTION_WAITERS.with(|waiters| waiters.borrow_mut().push(on_done));
+    }
+    if DISTRIBUTION_REFRESH_RUNNING.with(|running| running.replace(true)) {
+        DISTRIBUTION_RERUN_REQUESTED.with(|rerun| rerun.set(true));
+        return;
+    }
+
+    let active = badge_rules::get_badge_rules();
+    let candidate = badge_migration::candidate_rules();
+    let rule_sets: Vec<&badge_rules::BadgeRuleSet> = std::iter::once(&active).chain(candidate.as_ref()).collect();
+    count_distribution_batch(None, badge_rules::DistributionCounts::new(&rule_sets));
+}
+
+fn count_distribution_batch(cursor: Option<String>, mut counts: badge_rules::DistributionCounts) {
+    let batch: Vec<(String, GitHubAnalysis)> = GITHUB_ANALYSES.with(|analyses| {
+        let start = cursor.map_or(Bound::Unbounded, Bound::Excluded);
+        analyses.borrow().range((start, Bound::Unbounded)).take(REFRESH_BATCH_SIZE).collect()
+    });
+    let full = batch.len() == REFRESH_BATCH_SIZE;
+    let last = batch.last().map(|(username, _)| username.clone());
+    counts.add_analyses(batch.into_iter().map(|(_, analysis)| analysis));
+
+    match last {
+        Some(last) if full => {
+            ic_cdk_timers::set_timer(REFRESH_BATCH_DELAY, move || count_distribution_batch(Some(last), counts));
+        }
+        _ => {
+            badge_rules::set_metric_distributions(counts, ic_cdk::api::time());
+            DISTRIBUTION_REFRESH_RUNNING.with(|running| running.set(false));
+
+            if DISTRIBUTION_RERUN_REQUESTED.with(|rerun| rerun.replace(false)) {
+                refresh_metric_distributions(None);
+                return;
+            }
+            let waiters = DISTRIBUTION_WAITERS.with(|waiters| std::mem::take(&mut *waiters.borrow_mut()));
+            for on_done in waiters {
+                on_done();
+            }
+        }
+    }
+}
+
+/// Re-evaluate the next batch of profiles against their cached analyses under the target rule
+/// set, then schedule the following batch. Dry runs only add to the report.
+fn run_badge_migration_batch() {
+    let Some(status) = badge_migration::running_migration() else {
+        return;
+    };
+    let now = ic_cdk::api::time();
+
+    let rules_version = badge_rules::get_badge_rules().version;
+    if rules_version != status.target_version {
+        let reason = format!("Badge rules changed to version {} during the migration", rules_version);
+        let _ = badge_migration::finish_migration(badge_migration::BadgeMigrationState::Aborted(reason), now);
+        return;
+    }
+
+    let batch: Vec<(Principal, UserProfile)> = USER_PROFILES.with(|profiles| {
+        let profiles = profiles.borrow();
+        let start = status.cursor.map_or(Bound::Unbounded, Bound::Excluded);
+        profiles.range((start, Bound::Unbounded)).take(status.batch_size as usize).collect()
+    });
+
+    // A dry run may preview a candidate rule set instead of the active one
+    let rule_set = badge_migration::candidate_rules().unwrap_or_else(badge_rules::get_badge_rules);
+    for (user, profile) in &batch {
+        migrate_profile_badges(*user, profile.clone(), &status, &rule_set, now);
+    }
+
+    match batch.last() {
+        Some((last, _)) if batch.len() == status.batch_size as usize => {
+            badge_migration::record_batch(*last);
+            ic_cdk_timers::set_timer(MIGRATION_BATCH_DELAY, run_badge_migration_batch);
+        }
+        _ => {
+            if let Ok(finished) = badge_migration::finish_migration(badge_migration::BadgeMigrationState::Completed, now) {
+                ic_cdk::println!("Badge migration to rule set version {} completed (dry run: {}): {:?}",
+                                 finished.target_version, finished.dry_run, finished.report);
+            }
+        }
+    }
+}
+
+/// Re-evaluate one profile's rule-based badges; AI and seasonal badges are left as they are
+fn migrate_profile_badges(
+    user: Principal,
+    mut profile: UserProfile,
+    status: &badge_migration::BadgeMigrationStatus,
+    rule_set: &badge_rules::BadgeRuleSet,
+    now: u64,
+) {
+    let Some(analysis) = GITHUB_ANALYSES.with(|cache| cache.borrow().get(&profile.github_username))
+        .filter(|_| profile.github_connected) else {
+        badge_migration::record_skipped(false);
+        return;
+    };
+
+    let up_to_date = !profile.badges.is_empty()
+        && profile.badges.iter()
+            .filter(|b| reevaluated_by_analysis(b, false))
+            .all(|b| b.rule_version >= Some(status.target_version));
+    if up_to_date && !status.include_up_to_date {
+        badge_migration::record_skipped(true);
+        return;
+    }
+
+    let analysis_hash = utils::analysis_hash(&analysis);
+    let mut badges = utils::generate_badges_from_analysis(&analysis, rule_set);
+    stamp_evidence(&mut badges, &analysis, &analysis_hash, rule_set.version);
+
+    let previous_snapshots = referenced_snapshots(&profile);
+    let update = update_profile_badges(&mut profile, badges, &analysis, &analysis_hash, false, rule_set, now);
+    badge_migration::record_profile(&badge_migration::ProfileMigration {
+        gained: &update.progress.awarded,
+        upgraded: &update.progress.upgraded,
+        downgraded: &update.reconciliation.downgraded,
+        no_longer_met: &update.reconciliation.unsupported,
+        revoked: update.reconciliation.revoked.iter().map(|r| r.badge.id.as_str()).collect(),
+    });
+
+    if status.dry_run {
+        return;
+    }
+
+    // Badges confirmed under the new rules are re-stamped, so the profile is stored even without changes
+    store_profile(user, &profile);
+    store_evidence_snapshot(&previous_snapshots, &profile, &analysis_hash, &analysis);
+
+    ic_cdk::futures::spawn(async move {
+        sync_badge_nfts(user, profile, &update).await;
+    });
 }
 
 #[update]
@@ -92,11 +515,13 @@ async fn create_initial_profile() -> Result<UserProfile, String> {
         reputation_score: 0,
         badges: Vec::new(),
         total_badges: 0,
+        revoked_badges: None,
+        lifetime_reputation: None,
+        current_reputation: None,
+        reputation_breakdown: None,
     };
 
-    USER_PROFILES.with(|profiles| {
-        profiles.borrow_mut().insert(caller, profile.clone());
-    });
+    store_profile(caller, &profile);
 
     ic_cdk::println!("Initial profile created for user: {}", caller.to_text());
     Ok(profile)
@@ -126,6 +551,10 @@ async fn connect_github_oauth(oauth_request: GitHubOAuthRequest) -> Result<UserP
             reputation_score: 0,
             badges: Vec::new(),
             total_badges: 0,
+            revoked_badges: None,
+            lifetime_reputation: None,
+            current_reputation: None,
+            reputation_breakdown: None,
         }
     });
 
@@ -139,74 +568,342 @@ async fn connect_github_oauth(oauth_request: GitHubOAuthRequest) -> Result<UserP
     let github_data = github::fetch_github_user(&oauth_response.access_token).await
         .map_err(|e| format!("Failed to fetch GitHub user data: {}", e))?;
 
-    // Perform comprehensive GitHub analysis
-    let analysis = github::perform_comprehensive_analysis(&github_data.login, Some(&oauth_response.access_token)).await
+    // Analyze GitHub activity, enhance with Azure OpenAI and generate badges
+    let outcome = analyze_github_profile(caller, &github_data.login, Some(&oauth_response.access_token)).await
         .map_err(|e| format!("GitHub analysis failed: {}", e))?;
 
+    // Update profile
+    profile.github_username = github_data.login.clone();
+    profile.github_connected = true;
+    profile.github_data = Some(github_data);
+
+    let profile = apply_analysis_to_profile(caller, profile, outcome).await;
+
+    ic_cdk::println!("✅ GitHub connected successfully for user: {}, badges earned: {}",
+                     caller.to_text(), profile.badges.len());
+
+    Ok(profile)
+}
+
+// Result of one run of the analysis pipeline
+struct AnalysisOutcome {
+    analysis: GitHubAnalysis,
+    analysis_hash: String,
+    ai_analysis: Option<llm::LLMAnalysis>,
+    badges: Vec<Badge>,
+}
+
+/// Single analysis pipeline shared by connect, sync and admin sync:
+/// GitHub analysis, the LLM step (cached and budgeted) and badge generation
+async fn analyze_github_profile(
+    user: Principal,
+    github_username: &str,
+    access_token: Option<&str>
+) -> Result<AnalysisOutcome, String> {
+    let analysis = github::perform_comprehensive_analysis(github_username, access_token).await
+        .map_err(|e| e.to_string())?;
+
     // 🤖 Enhance analysis with Azure OpenAI
-    let enhanced_analysis = match llm::analyze_code_quality(&analysis).await {
+    let enhanced_analysis = match llm::analyze_code_quality(user, &analysis).await {
         Ok(llm_result) => {
-            ic_cdk::println!("✅ Azure OpenAI analysis successful for user: {}", github_data.login);
+            ic_cdk::println!("✅ Azure OpenAI analysis successful for user: {}", github_username);
             Some(llm_result)
         },
         Err(e) => {
-            ic_cdk::println!("⚠️ Azure OpenAI analysis failed: {}, using fallback", e);
+            ic_cdk::println!("⚠️ Azure OpenAI analysis failed: {}, awarding metric-based badges only", e);
             None
         }
     };
 
-    // Generate badges based on analysis (now enhanced with AI insights)
-    let new_badges = if let Some(ai_analysis) = enhanced_analysis {
-        generate_badges_from_enhanced_analysis(&analysis, &ai_analysis)
+    // Generate badges based on analysis (enhanced with AI insights when available); percentile
+    // tiers compare against the distribution snapshot kept current by the refresh timer
+    let now = ic_cdk::api::time();
+    let rule_set = badge_rules::get_badge_rules();
+    let mut badges = if let Some(ai_analysis) = &enhanced_analysis {
+        generate_badges_from_enhanced_analysis(&analysis, ai_analysis, &rule_set)
     } else {
-        utils::generate_badges_from_analysis(&analysis)
+        utils::generate_badges_from_analysis(&analysis, &rule_set)
     };
 
-    // Update profile
-    profile.github_username = github_data.login.clone();
-    profile.github_connected = true;
-    profile.github_data = Some(github_data);
-    profile.badges.extend(new_badges.clone());
-    profile.total_badges = profile.badges.len() as u32;
-    profile.reputation_score = utils::calculate_reputation_score(&profile.badges);
-    profile.last_github_sync = Some(ic_cdk::api::time());
-    profile.updated_at = ic_cdk::api::time();
+    // Seasonal badges can only be earned while their season is running
+    badges.extend(seasons::evaluate_season_badges(&analysis, now));
+
+    // Point every badge's evidence at the snapshot it was derived from
+    let analysis_hash = utils::analysis_hash(&analysis);
+    stamp_evidence(&mut badges, &analysis, &analysis_hash, rule_set.version);
+
+    Ok(AnalysisOutcome { analysis, analysis_hash, ai_analysis: enhanced_analysis, badges })
+}
+
+/// Merge freshly generated badges into the profile, persist it, cache the analysis and mint new NFTs
+async fn apply_analysis_to_profile(user: Principal, mut profile: UserProfile, outcome: AnalysisOutcome) -> UserProfile {
+    let now = ic_cdk::api::time();
+    let previous_snapshots = referenced_snapshots(&profile);
+
+    // AI badges are only re-checked when the LLM actually evaluated the profile in this run;
+    // an outage or exhausted budget leaves held AI badges alone
+    let update = update_profile_badges(
+        &mut profile,
+        outcome.badges,
+        &outcome.analysis,
+        &outcome.analysis_hash,
+        outcome.ai_analysis.is_some(),
+        &badge_rules::get_badge_rules(),
+        now,
+    );
+    profile.last_github_sync = Some(now);
 
     // Store updated profile
-    USER_PROFILES.with(|profiles| {
-        profiles.borrow_mut().insert(caller, profile.clone());
-    });
+    store_profile(user, &profile);
 
-    // Cache the analysis
+    // Cache the analysis, and keep it as the evidence snapshot badges refer to
+    store_evidence_snapshot(&previous_snapshots, &profile, &outcome.analysis_hash, &outcome.analysis);
     GITHUB_ANALYSES.with(|cache| {
-        cache.borrow_mut().insert(profile.github_username.clone(), analysis);
+        cache.borrow_mut().insert(profile.github_username.clone(), outcome.analysis);
     });
 
-    // Mint new badges as NFTs
-    for badge in new_badges {
-        if let Err(e) = mint_badge_nft(caller, &badge).await {
-            ic_cdk::println!("Failed to mint badge NFT for {}: {}", badge.name, e);
+    // Insights are only replaced by an actual model result
+    if let Some(ai_analysis) = outcome.ai_analysis.as_ref().filter(|a| a.model_version.is_some()) {
+        store_ai_insights(user, ai_analysis);
+    }
+
+    sync_badge_nfts(user, profile, &update).await
+}
+
+/// Analysis snapshots a profile's badge evidence points to, including that of revoked badges
+fn referenced_snapshots(profile: &UserProfile) -> BTreeSet<String> {
+    let revoked = profile.revoked_badges.iter().flatten().map(|r| &r.badge);
+    profile.badges.iter()
+        .chain(revoked)
+        .filter_map(|b| b.evidence.as_ref().map(|e| e.analysis_hash.clone()))
+        .filter(|hash| !hash.is_empty())
+        .collect()
+}
+
+/// Keep the snapshot the profile's evidence now points to, and drop the ones its evidence moved
+/// away from. Snapshot hashes cover the GitHub username, so they are never shared between accounts.
+fn store_evidence_snapshot(previous: &BTreeSet<String>, profile: &UserProfile, analysis_hash: &str, analysis: &GitHubAnalysis) {
+    let current = referenced_snapshots(profile);
+    ANALYSIS_SNAPSHOTS.with(|snapshots| {
+        let mut snapshots = snapshots.borrow_mut();
+        if current.contains(analysis_hash) && !snapshots.contains_key(&analysis_hash.to_string()) {
+            snapshots.insert(analysis_hash.to_string(), analysis.clone());
+        }
+        for hash in previous.difference(&current) {
+            snapshots.remove(hash);
         }
+    });
+}
+
+// Badge changes made to a profile by one evaluation
+#[derive(Default)]
+struct BadgeUpdate {
+    progress: BadgeProgress,
+    reconciliation: BadgeReconciliation,
+    merged: MergedBadgeNfts,
+}
+
+/// Apply freshly generated badges to a profile: downgrade or revoke held badges, award and
+/// upgrade, then do the same for composite badges and refresh totals. Does not persist anything.
+fn update_profile_badges(
+    profile: &mut UserProfile,
+    generated: Vec<Badge>,
+    analysis: &GitHubAnalysis,
+    analysis_hash: &str,
+    ai_evaluated: bool,
+    rule_set: &badge_rules::BadgeRuleSet,
+    now: u64,
+) -> BadgeUpdate {
+    // Held badges may predate canonical ids; fresh badges are matched against them by id
+    let (_, merged) = canonicalize_badge_ids(&mut profile.badges);
+
+    // Downgrade or revoke held badges the fresh data no longer supports. Seasonal badges are
+    // kept for good, and AI badges are left alone unless they were re-evaluated.
+    let policy = badge_rules::get_revocation_policy();
+    let composite_ids = badge_rules::composite_badge_ids(rule_set);
+    let mut reconciliation = reconcile_badges(
+        &mut profile.badges,
+        &generated,
+        &policy,
+        |badge| reevaluated_by_analysis(badge, ai_evaluated) && !composite_ids.contains(&badge.id),
+        now,
+    );
+
+    // Award new badges and upgrade held ones in place when a higher tier is reached
+    let mut progress = merge_badge_progress(&mut profile.badges, generated, now);
+
+    // Composite badges are evaluated against the updated base badges, then go through the same steps
+    let mut composites = badge_rules::evaluate_composite_badges(rule_set, &profile.badges, now);
+    stamp_evidence(&mut composites, analysis, analysis_hash, rule_set.version);
+    let composite_reconciliation = reconcile_badges(
+        &mut profile.badges,
+        &composites,
+        &policy,
+        |badge| composite_ids.contains(&badge.id),
+        now,
+    );
+    let composite_progress = merge_badge_progress(&mut profile.badges, composites, now);
+
+    reconciliation.downgraded.extend(composite_reconciliation.downgraded);
+    reconciliation.revoked.extend(composite_reconciliation.revoked);
+    reconciliation.unsupported.extend(composite_reconciliation.unsupported);
+    progress.awarded.extend(composite_progress.awarded);
+    progress.upgraded.extend(composite_progress.upgraded);
+
+    apply_rarity(&mut profile.badges);
+
+    if !reconciliation.revoked.is_empty() {
+        profile.revoked_badges.get_or_insert_with(Vec::new)
+            .extend(reconciliation.revoked.iter().cloned());
     }
 
-    ic_cdk::println!("✅ GitHub connected successfully for user: {}, badges earned: {}",
-                     caller.to_text(), profile.badges.len());
+    profile.total_badges = profile.badges.len() as u32;
+    reputation::apply_reputation(profile, &reputation::get_reputation_config(), now);
+    profile.updated_at = now;
 
-    Ok(profile)
+    BadgeUpdate { progress, reconciliation, merged }
+}
+
+/// Burn the NFTs of revoked and merged-away badges, mint new badges and refresh the NFTs of
+/// changed ones, then record minted token ids on the stored profile
+async fn sync_badge_nfts(user: Principal, profile: UserProfile, update: &BadgeUpdate) -> UserProfile {
+    let BadgeUpdate { progress, reconciliation, merged } = update;
+
+    if !progress.upgraded.is_empty() || !reconciliation.downgraded.is_empty() {
+        ic_cdk::println!("Badge changes for {}: upgraded {:?}, downgraded {:?}",
+                         user.to_text(), progress.upgraded, reconciliation.downgraded);
+    }
+
+    // Burn the NFTs of revoked badges
+    for revoked in &reconciliation.revoked {
+        ic_cdk::println!("Revoked badge {} for {}: {}", revoked.badge.id, user.to_text(), revoked.reason);
+        if let Some(token_id) = revoked.badge.nft_token_id {
+            if let Err(e) = burn_badge_nft(token_id, &revoked.reason).await {
+                ic_cdk::println!("Failed to burn badge NFT for {}: {}", revoked.badge.name, e);
+            }
+        }
+    }
+
+    // Burn the NFTs of duplicates merged into a canonical badge that already had one
+    for token_id in &merged.orphaned {
+        if let Err(e) = burn_badge_nft(*token_id, "Merged into a duplicate badge with a canonical id").await {
+            ic_cdk::println!("Failed to burn merged badge NFT {}: {}", token_id, e);
+        }
+    }
+
+    // Mint new badges as NFTs and refresh the NFTs of upgraded or downgraded ones
+    let changed: Vec<Badge> = profile.badges.iter()
+        .filter(|b| {
+            progress.awarded.contains(&b.id)
+                || progress.upgraded.contains(&b.id)
+                || reconciliation.downgraded.contains(&b.id)
+                || merged.relinked.contains(&b.id)
+        })
+        .cloned()
+        .collect();
+
+    let mut minted = Vec::new();
+    for badge in changed {
+        match badge.nft_token_id {
+            Some(token_id) => {
+                if let Err(e) = update_badge_nft(token_id, &badge).await {
+                    ic_cdk::println!("Failed to update badge NFT for {}: {}", badge.name, e);
+                }
+            }
+            None => match mint_badge_nft(user, &badge).await {
+                Ok(token_id) => minted.push((badge.id.clone(), token_id)),
+                Err(e) => ic_cdk::println!("Failed to mint badge NFT for {}: {}", badge.name, e),
+            },
+        }
+    }
+
+    if minted.is_empty() {
+        return profile;
+    }
+
+    // Record token ids on the stored profile, which may have changed while minting
+    USER_PROFILES.with(|profiles| {
+        let mut profiles = profiles.borrow_mut();
+        let mut stored = profiles.get(&user).unwrap_or(profile);
+        for badge in stored.badges.iter_mut() {
+            if let Some((_, token_id)) = minted.iter().find(|(id, _)| *id == badge.id) {
+                badge.nft_token_id = Some(*token_id);
+            }
+        }
+        profiles.insert(user, stored.clone());
+        stored
+    })
+}
+
+/// Persist the latest insights, keeping the user's visibility choice
+fn store_ai_insights(user: Principal, ai_analysis: &llm::LLMAnalysis) -> AIInsights {
+    let hidden = AI_INSIGHTS.with(|insights| {
+        insights.borrow().get(&user).map(|existing| existing.hidden).unwrap_or(false)
+    });
+
+    let insights = AIInsights {
+        user_principal: user,
+        analysis_summary: ai_analysis.analysis_summary.clone(),
+        strengths: ai_analysis.strengths.clone(),
+        improvement_areas: ai_analysis.improvement_areas.clone(),
+        recommended_badges: ai_analysis.recommended_badges.clone(),
+        expertise_areas: ai_analysis.expertise_areas.clone(),
+        model_version: ai_analysis.model_version.clone(),
+        generated_at: ic_cdk::api::time(),
+        hidden,
+    };
+
+    AI_INSIGHTS.with(|store| {
+        store.borrow_mut().insert(user, insights.clone());
+    });
+
+    insights
 }
 
 // Enhanced badge generation using Azure OpenAI insights
 fn generate_badges_from_enhanced_analysis(
     github_analysis: &GitHubAnalysis,
-    ai_analysis: &llm::LLMAnalysis
+    ai_analysis: &llm::LLMAnalysis,
+    rule_set: &badge_rules::BadgeRuleSet,
 ) -> Vec<Badge> {
-    let mut badges = utils::generate_badges_from_analysis(github_analysis);
+    let mut badges = utils::generate_badges_from_analysis(github_analysis, rule_set);
     let current_time = ic_cdk::api::time();
 
+    // The metric-based fallback is not an AI evaluation and never awards AI badges
+    if ai_analysis.model_version.is_none() {
+        return badges;
+    }
+
+    // With multi-sample scoring, only award AI badges the samples agree on; a result without
+    // consensus data only counts when a single sample was asked for
+    let consensus_config = llm::get_llm_consensus_config();
+    let agreed = |metric: &str| match &ai_analysis.consensus {
+        Some(consensus) => consensus.agrees_on(metric, consensus_config.tolerance),
+        None => consensus_config.samples <= 1,
+    };
+    let consensus_note = |metric: &str| {
+        ai_analysis.consensus.as_ref().and_then(|consensus| {
+            consensus.scores.iter().find(|s| s.metric == metric).map(|s| {
+                format!("Consensus of {} samples (spread {:.1}, variance {:.1})",
+                        consensus.sample_count, s.spread, s.variance)
+            })
+        })
+    };
+
+    let ai_evidence = |metric: &str, score: f32| {
+        let samples = ai_analysis.consensus.as_ref().map(|c| c.sample_count).unwrap_or(1);
+        BadgeEvidence::from_inputs(&[
+            ("metric", metric.to_string()),
+            ("value", format!("{:.1}", score)),
+            ("samples", samples.to_string()),
+            ("model_version", ai_analysis.model_version.clone().unwrap_or_else(|| "fallback".to_string())),
+        ])
+    };
+
     // Add AI-powered special badges based on Azure OpenAI insights
 
     // AI Quality Badge
-    if ai_analysis.code_quality_score >= 85.0 {
+    if ai_analysis.code_quality_score >= AI_QUALITY_MASTER_MIN_SCORE && agreed("code_quality_score") {
         badges.push(Badge {
             id: "ai_quality_master".to_string(),
             name: "AI Quality Master".to_string(),
@@ -214,8 +911,18 @@ fn generate_badges_from_enhanced_analysis(
             category: BadgeCategory::Special("AI-Verified".to_string()),
             tier: BadgeTier::Gold3,
             earned_at: current_time,
-            criteria_met: vec![format!("AI Quality Score: {:.1}", ai_analysis.code_quality_score)],
+            criteria_met: std::iter::once(format!("AI Quality Score: {:.1}", ai_analysis.code_quality_score))
+                .chain(consensus_note("code_quality_score"))
+                .collect(),
             score_achieved: ai_analysis.code_quality_score as u32,
+            upgraded_at: None,
+            tier_history: None,
+            nft_token_id: None,
+            at_risk_since: None,
+            season: None,
+            rule_version: None,
+            last_confirmed_at: None,
+            evidence: Some(ai_evidence("code_quality_score", ai_analysis.code_quality_score)),
             metadata: BadgeMetadata {
                 image_url: "/badges/special/ai_quality_master.svg".to_string(),
                 animation_url: Some("/badges/special/ai_quality_master_animated.gif".to_string()),
@@ -237,7 +944,7 @@ fn generate_badges_from_enhanced_analysis(
     }
 
     // Innovation Badge
-    if ai_analysis.innovation_score >= 80.0 {
+    if ai_analysis.innovation_score >= AI_INNOVATOR_MIN_SCORE && agreed("innovation_score") {
         badges.push(Badge {
             id: "ai_innovator".to_string(),
             name: "AI-Verified Innovator".to_string(),
@@ -245,8 +952,18 @@ fn generate_badges_from_enhanced_analysis(
             category: BadgeCategory::Special("Innovation".to_string()),
             tier: BadgeTier::Gold2,
             earned_at: current_time,
-            criteria_met: vec![format!("AI Innovation Score: {:.1}", ai_analysis.innovation_score)],
+            criteria_met: std::iter::once(format!("AI Innovation Score: {:.1}", ai_analysis.innovation_score))
+                .chain(consensus_note("innovation_score"))
+                .collect(),
             score_achieved: ai_analysis.innovation_score as u32,
+            upgraded_at: None,
+            tier_history: None,
+            nft_token_id: None,
+            at_risk_since: None,
+            season: None,
+            rule_version: None,
+            last_confirmed_at: None,
+            evidence: Some(ai_evidence("innovation_score", ai_analysis.innovation_score)),
             metadata: BadgeMetadata {
                 image_url: "/badges/special/ai_innovator.svg".to_string(),
                 animation_url: Some("/badges/special/ai_innovator_animated.gif".to_string()),
@@ -267,11 +984,12 @@ fn generate_badges_from_enhanced_analysis(
         });
     }
 
-    // Expertise badges based on AI-identified areas
+    // Expertise badges based on AI-identified areas not already covered by a language or expertise badge
     for expertise in &ai_analysis.expertise_areas {
-        if !badges.iter().any(|b| b.name.contains(expertise)) {
+        let slug = badge_ids::canonical_slug(expertise);
+        if !slug.is_empty() && !badges.iter().any(|b| badge_ids::badge_subject(&b.id) == Some(slug.as_str())) {
             badges.push(Badge {
-                id: format!("ai_expert_{}", expertise.to_lowercase().replace(" ", "_")),
+                id: badge_ids::ai_expert_badge_id(expertise),
                 name: format!("AI-Verified {} Expert", expertise),
                 description: format!("Expertise in {} verified by AI analysis", expertise),
                 category: BadgeCategory::Language(expertise.clone()),
@@ -279,8 +997,19 @@ fn generate_badges_from_enhanced_analysis(
                 earned_at: current_time,
                 criteria_met: vec![format!("AI-identified expertise in {}", expertise)],
                 score_achieved: 100,
+                upgraded_at: None,
+                tier_history: None,
+                nft_token_id: None,
+                at_risk_since: None,
+                season: None,
+                rule_version: None,
+                last_confirmed_at: None,
+                evidence: Some(BadgeEvidence::from_inputs(&[
+                    ("expertise_area", expertise.clone()),
+                    ("model_version", ai_analysis.model_version.clone().unwrap_or_else(|| "fallback".to_string())),
+                ])),
                 metadata: BadgeMetadata {
-                    image_url: format!("/badges/ai_expertise/{}.svg", expertise.to_lowercase().replace(" ", "_")),
+                    image_url: format!("/badges/ai_expertise/{}.svg", slug),
                     animation_url: None,
                     attributes: vec![
                         BadgeAttribute {
@@ -312,7 +1041,7 @@ async fn sync_github_data() -> Result<UserProfile, String> {
     let caller = ic_cdk::caller();
 
     // Get existing profile
-    let mut profile = USER_PROFILES.with(|profiles| {
+    let profile = USER_PROFILES.with(|profiles| {
         profiles.borrow().get(&caller)
     }).ok_or("Profile not found")?;
 
@@ -323,7 +1052,7 @@ async fn sync_github_data() -> Result<UserProfile, String> {
     // Rate limiting: max 1 sync per hour per user
     if let Some(last_sync) = profile.last_github_sync {
         let now = ic_cdk::api::time();
-        if now - last_sync < 3600_000_000_000 { // 1 hour in nanoseconds
+        if now - last_sync < SYNC_INTERVAL_NS {
             return Err("GitHub sync can only be triggered once per hour".to_string());
         }
     }
@@ -331,42 +1060,10 @@ async fn sync_github_data() -> Result<UserProfile, String> {
     ic_cdk::println!("Syncing GitHub data for user: {}", profile.github_username);
 
     // Perform fresh analysis (without access token for now - would need to store securely)
-    let analysis = github::perform_comprehensive_analysis(&profile.github_username, None).await
+    let outcome = analyze_github_profile(caller, &profile.github_username, None).await
         .map_err(|e| format!("GitHub sync failed: {}", e))?;
 
-    // Generate new badges (only add if not already earned)
-    let new_badges = generate_badges_from_analysis(&analysis);
-    let existing_badge_ids: std::collections::HashSet<String> = profile.badges.iter()
-        .map(|b| b.id.clone())
-        .collect();
-
-    let truly_new_badges: Vec<Badge> = new_badges.into_iter()
-        .filter(|b| !existing_badge_ids.contains(&b.id))
-        .collect();
-
-    // Update profile
-    profile.badges.extend(truly_new_badges.clone());
-    profile.total_badges = profile.badges.len() as u32;
-    profile.reputation_score = calculate_reputation_score(&profile.badges);
-    profile.last_github_sync = Some(ic_cdk::api::time());
-    profile.updated_at = ic_cdk::api::time();
-
-    // Store updated profile
-    USER_PROFILES.with(|profiles| {
-        profiles.borrow_mut().insert(caller, profile.clone());
-    });
-
-    // Update cached analysis
-    GITHUB_ANALYSES.with(|cache| {
-        cache.borrow_mut().insert(profile.github_username.clone(), analysis);
-    });
-
-    // Mint new badges as NFTs
-    for badge in truly_new_badges {
-        if let Err(e) = mint_badge_nft(caller, &badge).await {
-            ic_cdk::println!("Failed to mint badge NFT for {}: {}", badge.name, e);
-        }
-    }
+    let profile = apply_analysis_to_profile(caller, profile, outcome).await;
 
     ic_cdk::println!("GitHub sync completed for user: {}, total badges: {}",
                      profile.github_username, profile.badges.len());
@@ -386,9 +1083,7 @@ async fn disconnect_github() -> Result<UserProfile, String> {
     profile.github_data = None;
     profile.updated_at = ic_cdk::api::time();
 
-    USER_PROFILES.with(|profiles| {
-        profiles.borrow_mut().insert(caller, profile.clone());
-    });
+    store_profile(caller, &profile);
 
     ic_cdk::println!("GitHub disconnected for user: {}", caller.to_text());
     Ok(profile)
@@ -412,24 +1107,145 @@ fn get_badges(user: Option<Principal>) -> Vec<Badge> {
     })
 }
 
+/// Public provenance for a held badge: its evidence and the analysis snapshot it was derived from
 #[query]
-fn get_leaderboard(limit: Option<u32>) -> Vec<UserProfile> {
-    let limit = limit.unwrap_or(100).min(1000) as usize;
+fn get_badge_evidence(user: Principal, badge_id: String) -> Option<BadgeEvidenceReport> {
+    let badge_id = badge_ids::canonical_badge_id(&badge_id);
+    let badge = USER_PROFILES.with(|profiles| {
+        profiles.borrow().get(&user)
+            .and_then(|profile| {
+                profile.badges.into_iter().find(|b| badge_ids::canonical_badge_id(&b.id) == badge_id)
+            })
+    })?;
 
-    USER_PROFILES.with(|profiles| {
-        let mut all_profiles: Vec<UserProfile> = profiles
-            .borrow()
-            .iter()
-            .filter(|(_, profile)| profile.github_connected) // Only include GitHub-connected users
-            .map(|(_, profile)| profile)
-            .collect();
-
-        all_profiles.sort_by(|a, b| b.reputation_score.cmp(&a.reputation_score));
-        all_profiles.truncate(limit);
-        all_profiles
+    let snapshot = badge.evidence.as_ref().and_then(|evidence| {
+        ANALYSIS_SNAPSHOTS.with(|snapshots| snapshots.borrow().get(&evidence.analysis_hash))
+    });
+
+    Some(BadgeEvidenceReport { badge, snapshot })
+}
+
+/// Preview the badges an analysis would earn and how far each badge is from its next tier.
+/// Nothing is stored; percentile tiers use the distributions last computed by the refresh timer.
+#[query]
+fn simulate_badges(request: BadgeSimulationRequest) -> Result<BadgeSimulation, String> {
+    let mut analysis = match request.analysis {
+        Some(analysis) => analysis,
+        None => {
+            let caller = ic_cdk::caller();
+            let profile = USER_PROFILES.with(|profiles| profiles.borrow().get(&caller))
+                .ok_or("Profile not found")?;
+            GITHUB_ANALYSES.with(|cache| cache.borrow().get(&profile.github_username))
+                .ok_or("No GitHub analysis cached for this profile, sync GitHub data first")?
+        }
+    };
+    if let Some(deltas) = &request.deltas {
+        utils::apply_analysis_deltas(&mut analysis, deltas);
+    }
+
+    let now = ic_cdk::api::time();
+    let rule_set = badge_rules::get_badge_rules();
+    let mut badges = utils::generate_badges_from_analysis(&analysis, &rule_set);
+    badges.extend(seasons::evaluate_season_badges(&analysis, now));
+    let composites = badge_rules::evaluate_composite_badges(&rule_set, &badges, now);
+    badges.extend(composites);
+
+    let mut progress = utils::evaluate_language_progress(&analysis);
+    progress.extend(badge_rules::evaluate_rule_progress(&analysis));
+    progress.extend(seasons::evaluate_season_progress(&analysis, now));
+
+    Ok(BadgeSimulation {
+        reputation_score: calculate_reputation_score(&badges),
+        badges,
+        progress,
+    })
+}
+
+#[query]
+fn get_ai_insights(user: Option<Principal>) -> Option<AIInsights> {
+    let caller = ic_cdk::caller();
+    let target = user.unwrap_or(caller);
+
+    AI_INSIGHTS.with(|insights| {
+        insights.borrow().get(&target)
+            // Hidden insights are only visible to their owner
+            .filter(|insights| !insights.hidden || target == caller)
     })
 }
 
+#[update]
+fn set_ai_insights_hidden(hidden: bool) -> Result<AIInsights, String> {
+    let caller = ic_cdk::caller();
+
+    let mut insights = AI_INSIGHTS.with(|store| store.borrow().get(&caller))
+        .ok_or("No AI insights found for this user")?;
+
+    insights.hidden = hidden;
+
+    AI_INSIGHTS.with(|store| {
+        store.borrow_mut().insert(caller, insights.clone());
+    });
+
+    Ok(insights)
+}
+
+#[update]
+async fn regenerate_ai_insights() -> Result<AIInsights, String> {
+    let caller = ic_cdk::caller();
+
+    let profile = USER_PROFILES.with(|profiles| {
+        profiles.borrow().get(&caller)
+    }).ok_or("Profile not found")?;
+
+    if !profile.github_connected {
+        return Err("GitHub account not connected".to_string());
+    }
+
+    if let Some(existing) = AI_INSIGHTS.with(|store| store.borrow().get(&caller)) {
+        if ic_cdk::api::time() - existing.generated_at < SYNC_INTERVAL_NS {
+            return Err("AI insights can only be regenerated once per hour".to_string());
+        }
+    }
+
+    let analysis = GITHUB_ANALYSES.with(|analyses| {
+        analyses.borrow().get(&profile.github_username)
+    }).ok_or("No GitHub analysis available, sync GitHub data first")?;
+
+    // A failed run keeps the stored insights and does not count towards the hourly limit
+    let ai_analysis = llm::regenerate_code_quality(caller, &analysis).await
+        .map_err(|e| format!("AI insights could not be regenerated, try again later: {}", e))?;
+    if ai_analysis.model_version.is_none() {
+        return Err("AI insights could not be regenerated, try again later".to_string());
+    }
+    Ok(store_ai_insights(caller, &ai_analysis))
+}
+
+/// One page of GitHub-connected users by reputation; pass the returned cursor to continue
+#[query]
+fn get_leaderboard(cursor: Option<leaderboard::LeaderboardKey>, limit: Option<u32>) -> leaderboard::LeaderboardPage {
+    leaderboard::get_page(cursor, limit)
+}
+
+#[query]
+fn get_leaderboard_rank(user: Option<Principal>) -> Option<leaderboard::LeaderboardEntry> {
+    leaderboard::get_entry(user.unwrap_or_else(ic_cdk::caller))
+}
+
+/// One page of a language, category or time-window leaderboard
+#[query]
+fn get_scoped_leaderboard(
+    scope: leaderboard::LeaderboardScope,
+    cursor: Option<leaderboard::LeaderboardKey>,
+    limit: Option<u32>,
+) -> Result<leaderboard::LeaderboardPage, String> {
+    leaderboard::get_scoped_page(&scope, cursor, limit, ic_cdk::api::time())
+}
+
+#[query]
+fn get_scoped_leaderboard_rank(scope: leaderboard::LeaderboardScope, user: Option<Principal>) -> Result<Option<leaderboard::LeaderboardEntry>, String> {
+    leaderboard::get_scoped_entry(&scope, user.unwrap_or_else(ic_cdk::caller), ic_cdk::api::time())
+}
+
 #[query]
 fn get_github_analysis(username: String) -> Option<GitHubAnalysis> {
     GITHUB_ANALYSES.with(|analyses| {
@@ -437,14 +1253,67 @@ fn get_github_analysis(username: String) -> Option<GitHubAnalysis> {
     })
 }
 
+#[query]
+fn get_badge_rules() -> badge_rules::BadgeRuleSet {
+    badge_rules::get_badge_rules()
+}
+
+#[query]
+fn get_reputation_breakdown(user: Option<Principal>) -> Option<ReputationBreakdown> {
+    let target = user.unwrap_or_else(ic_cdk::caller);
+    USER_PROFILES.with(|profiles| {
+        profiles.borrow().get(&target).and_then(|profile| profile.reputation_breakdown)
+    })
+}
+
+/// Where a user's reputation falls overall, per language and category, and within their cohorts
+#[query]
+fn get_percentiles(user: Option<Principal>) -> Result<percentiles::UserPercentiles, String> {
+    let target = user.unwrap_or_else(ic_cdk::caller);
+    let profile = USER_PROFILES.with(|profiles| profiles.borrow().get(&target))
+        .ok_or("Profile not found")?;
+    percentiles::user_percentiles(&profile, ic_cdk::api::time())
+}
+
+#[query]
+fn get_reputation_config() -> reputation::ReputationConfig {
+    reputation::get_reputation_config()
+}
+
+#[query]
+fn get_badge_revocation_policy() -> badge_rules::BadgeRevocationPolicy {
+    badge_rules::get_revocation_policy()
+}
+
+#[query]
+fn get_seasons() -> Vec<seasons::Season> {
+    seasons::get_seasons()
+}
+
+#[query]
+fn get_badge_rarity(badge_id: Option<String>) -> rarity::RarityTable {
+    let mut table = rarity::get_rarity_table();
+    if let Some(badge_id) = badge_id {
+        let badge_id = badge_ids::canonical_badge_id(&badge_id);
+        table.entries.retain(|e| e.badge_id == badge_id);
+    }
+    table
+}
+
+#[query]
+fn get_badge_catalog() -> catalog::BadgeCatalog {
+    catalog::build_catalog(&seasons::get_seasons(), &rarity::get_rarity_table(), ic_cdk::api::time())
+}
+
 #[query]
 fn get_badge_statistics() -> Vec<(String, u32)> {
     let mut badge_counts = std::collections::HashMap::new();
 
+    // Grouped by canonical id, so legacy ids and renamed badges count as one
     USER_PROFILES.with(|profiles| {
         for (_, profile) in profiles.borrow().iter() {
             for badge in &profile.badges {
-                *badge_counts.entry(badge.name.clone()).or_insert(0) += 1;
+                *badge_counts.entry(badge_ids::canonical_badge_id(&badge.id)).or_insert(0) += 1;
             }
         }
     });
@@ -458,6 +1327,7 @@ fn get_badge_statistics() -> Vec<(String, u32)> {
 #[update]
 async fn validate_github_username(username: String) -> Result<bool, String> {
     github::validate_github_username(&username).await
+        .map_err(|e| e.to_string())
 }
 
 #[update]
@@ -470,7 +1340,7 @@ async fn get_github_oauth_url(state: String) -> Result<String, String> {
 // Admin functions
 #[update(guard = "is_admin")]
 async fn admin_force_github_sync(user: Principal) -> Result<String, String> {
-    let mut profile = USER_PROFILES.with(|profiles| {
+    let profile = USER_PROFILES.with(|profiles| {
         profiles.borrow().get(&user)
     }).ok_or("User profile not found")?;
 
@@ -479,28 +1349,141 @@ async fn admin_force_github_sync(user: Principal) -> Result<String, String> {
     }
 
     // Force analysis without rate limiting
-    let analysis = github::perform_comprehensive_analysis(&profile.github_username, None).await
+    let outcome = analyze_github_profile(user, &profile.github_username, None).await
         .map_err(|e| format!("GitHub analysis failed: {}", e))?;
 
-    let new_badges = generate_badges_from_analysis(&analysis);
+    apply_analysis_to_profile(user, profile, outcome).await;
 
-    profile.badges.extend(new_badges);
-    profile.total_badges = profile.badges.len() as u32;
-    profile.reputation_score = calculate_reputation_score(&profile.badges);
-    profile.last_github_sync = Some(ic_cdk::api::time());
-    profile.updated_at = ic_cdk::api::time();
+    Ok(format!("Force sync completed for user: {}", user.to_text()))
+}
 
-    let github_username = profile.github_username.clone();
+#[update(guard = "is_admin")]
+fn admin_clear_llm_cache() -> Result<String, String> {
+    let removed = llm::clear_llm_cache();
+    Ok(format!("Cleared {} cached LLM analyses", removed))
+}
 
-    USER_PROFILES.with(|profiles| {
-        profiles.borrow_mut().insert(user, profile);
-    });
+#[update(guard = "is_admin")]
+fn admin_set_llm_budget(config: llm::LLMBudgetConfig) -> Result<String, String> {
+    llm::set_llm_budget(config)?;
+    Ok("LLM budget updated".to_string())
+}
 
-    GITHUB_ANALYSES.with(|cache| {
-        cache.borrow_mut().insert(github_username, analysis);
-    });
+#[update(guard = "is_admin")]
+fn admin_set_llm_consensus(config: llm::LLMConsensusConfig) -> Result<String, String> {
+    llm::set_llm_consensus_config(config)?;
+    Ok("LLM consensus settings updated".to_string())
+}
 
-    Ok(format!("Force sync completed for user: {}", user.to_text()))
+#[update(guard = "is_admin")]
+fn admin_set_badge_rule(rule: badge_rules::BadgeRule) -> Result<String, String> {
+    let rule_set = badge_rules::upsert_badge_rule(rule)?;
+    Ok(format!("Badge rules updated to version {}", rule_set.version))
+}
+
+#[update(guard = "is_admin")]
+fn admin_remove_badge_rule(rule_id: String) -> Result<String, String> {
+    let rule_set = badge_rules::remove_badge_rule(&rule_id)?;
+    Ok(format!("Badge rule removed, rules now at version {}", rule_set.version))
+}
+
+#[update(guard = "is_admin")]
+fn admin_set_composite_badge(composite: badge_rules::CompositeBadgeRule) -> Result<String, String> {
+    let rule_set = badge_rules::upsert_composite_rule(composite)?;
+    Ok(format!("Composite badges updated, rules now at version {}", rule_set.version))
+}
+
+#[update(guard = "is_admin")]
+fn admin_remove_composite_badge(badge_id: String) -> Result<String, String> {
+    let rule_set = badge_rules::remove_composite_rule(&badge_id)?;
+    Ok(format!("Composite badge removed, rules now at version {}", rule_set.version))
+}
+
+#[update(guard = "is_admin")]
+fn admin_reset_badge_rules() -> Result<String, String> {
+    let rule_set = badge_rules::reset_badge_rules()?;
+    Ok(format!("Badge rules reset to defaults at version {}", rule_set.version))
+}
+
+#[update(guard = "is_admin")]
+fn admin_start_badge_migration(options: badge_migration::BadgeMigrationOptions) -> Result<String, String> {
+    let now = ic_cdk::api::time();
+    let target_version = badge_rules::get_badge_rules().version;
+    let status = badge_migration::start_migration(options, target_version, now)?;
+
+    // Evaluate every batch against the same, freshly computed percentile distributions
+    refresh_metric_distributions(Some(run_badge_migration_batch));
+
+    if status.previews_candidate {
+        return Ok(format!("Dry run previewing a candidate rule set against version {} started", status.target_version));
+    }
+    Ok(format!("Badge migration to rule set version {} started{}",
+               status.target_version, if status.dry_run { " as a dry run" } else { "" }))
+}
+
+#[update(guard = "is_admin")]
+fn admin_cancel_badge_migration() -> Result<String, String> {
+    let status = badge_migration::finish_migration(badge_migration::BadgeMigrationState::Cancelled, ic_cdk::api::time())?;
+    Ok(format!("Badge migration cancelled after {} profiles", status.report.profiles_scanned))
+}
+
+#[query(guard = "is_admin")]
+fn get_badge_migration_status() -> Option<badge_migration::BadgeMigrationStatus> {
+    badge_migration::get_migration_status()
+}
+
+#[update(guard = "is_admin")]
+fn admin_create_season(season: seasons::Season) -> Result<String, String> {
+    let season = seasons::create_season(season, ic_cdk::api::time())?;
+    Ok(format!("Season '{}' created", season.id))
+}
+
+#[update(guard = "is_admin")]
+fn admin_close_season(season_id: String) -> Result<String, String> {
+    let season = seasons::close_season(&season_id, ic_cdk::api::time())?;
+    Ok(format!("Season '{}' closed", season.id))
+}
+
+#[update(guard = "is_admin")]
+fn admin_set_badge_revocation_policy(policy: badge_rules::BadgeRevocationPolicy) -> Result<String, String> {
+    badge_rules::set_revocation_policy(policy)?;
+    Ok("Badge revocation policy updated".to_string())
+}
+
+#[update(guard = "is_admin")]
+fn admin_set_reputation_config(config: reputation::ReputationConfig) -> Result<String, String> {
+    reputation::set_reputation_config(config)?;
+    // Rescore everyone with the new settings rather than waiting for the daily refresh
+    ic_cdk_timers::set_timer(Duration::ZERO, refresh_reputation);
+    Ok("Reputation config updated, scores are being recomputed".to_string())
+}
+
+#[update(guard = "is_admin")]
+fn admin_rebuild_leaderboard() -> Result<String, String> {
+    rebuild_leaderboard();
+    Ok(format!("Leaderboard rebuilt with {} ranked profiles", leaderboard::index_len()))
+}
+
+#[update(guard = "is_admin")]
+fn admin_set_outcall_policy(policy: outcall::OutcallPolicy) -> Result<String, String> {
+    outcall::set_outcall_policy(policy)?;
+    Ok("Outcall policy updated".to_string())
+}
+
+#[update(guard = "is_admin")]
+fn admin_reset_circuit_breakers() -> Result<String, String> {
+    outcall::reset_circuits();
+    Ok("Circuit breakers reset".to_string())
+}
+
+#[query(guard = "is_admin")]
+fn get_outcall_status() -> outcall::OutcallStatus {
+    outcall::get_outcall_status()
+}
+
+#[query(guard = "is_admin")]
+fn get_llm_usage_report(days: Option<u32>) -> llm::LLMUsageReport {
+    llm::get_llm_usage_report(days.unwrap_or(7).clamp(1, llm::USAGE_RETENTION_DAYS))
 }
 
 // Utility functions
@@ -520,18 +1503,75 @@ async fn verify_authenticated(caller: Principal) -> Result<(), String> {
     Ok(())
 }
 
+/// Badge metadata in the NFT canister's `TokenMetadata` JSON shape
+fn badge_token_metadata(badge: &Badge) -> Result<String, String> {
+    let mut attributes = vec![
+        serde_json::json!({ "name": "badge_id", "value": badge.id }),
+        serde_json::json!({ "name": "tier", "value": badge.tier.get_display_name() }),
+        serde_json::json!({ "name": "score", "value": badge.score_achieved.to_string() }),
+        serde_json::json!({ "name": "earned_at", "value": badge.earned_at.to_string() }),
+        serde_json::json!({ "name": "rarity_score", "value": badge.metadata.rarity_score.to_string() }),
+    ];
+    if let Some(evidence) = &badge.evidence {
+        attributes.push(serde_json::json!({ "name": "evidence_hash", "value": evidence.analysis_hash }));
+    }
+    if let Some(season) = &badge.season {
+        attributes.push(serde_json::json!({ "name": "season", "value": season }));
+    }
+    if let Some(upgraded_at) = badge.upgraded_at {
+        attributes.push(serde_json::json!({ "name": "upgraded_at", "value": upgraded_at.to_string() }));
+    }
+    attributes.extend(badge.metadata.attributes.iter()
+        .map(|attr| serde_json::json!({ "name": attr.trait_type, "value": attr.value })));
+
+    let metadata = serde_json::json!({
+        "token_id": badge.nft_token_id.unwrap_or(0),
+        "name": format!("{} ({})", badge.name, badge.tier.get_display_name()),
+        "description": badge.description,
+        "image": badge.metadata.image_url,
+        "attributes": attributes,
+        "created_at": badge.earned_at,
+    });
+
+    serde_json::to_string(&metadata)
+        .map_err(|e| format!("Failed to serialize badge: {}", e))
+}
+
 async fn mint_badge_nft(user: Principal, badge: &Badge) -> Result<u64, String> {
     let nft_canister = NFT_CANISTER_ID.with(|id| *id.borrow())
         .ok_or("NFT canister not configured")?;
 
-    let metadata = serde_json::to_string(badge)
-        .map_err(|e| format!("Failed to serialize badge: {}", e))?;
+    let metadata = badge_token_metadata(badge)?;
 
-    let (token_id,): (u64,) = ic_cdk::call(nft_canister, "mint", (user, metadata))
+    let (result,): (Result<u64, String>,) = ic_cdk::call(nft_canister, "mint", (user, metadata))
         .await
         .map_err(|e| format!("Failed to mint NFT: {:?}", e))?;
 
-    Ok(token_id)
+    result
+}
+
+async fn update_badge_nft(token_id: u64, badge: &Badge) -> Result<(), String> {
+    let nft_canister = NFT_CANISTER_ID.with(|id| *id.borrow())
+        .ok_or("NFT canister not configured")?;
+
+    let metadata = badge_token_metadata(badge)?;
+
+    let (result,): (Result<(), String>,) = ic_cdk::call(nft_canister, "update_metadata", (token_id, metadata))
+        .await
+        .map_err(|e| format!("Failed to update NFT: {:?}", e))?;
+
+    result
+}
+
+async fn burn_badge_nft(token_id: u64, reason: &str) -> Result<(), String> {
+    let nft_canister = NFT_CANISTER_ID.with(|id| *id.borrow())
+        .ok_or("NFT canister not configured")?;
+
+    let (result,): (Result<(), String>,) = ic_cdk::call(nft_canister, "burn", (token_id, reason.to_string()))
+        .await
+        .map_err(|e| format!("Failed to burn NFT: {:?}", e))?;
+
+    result
 }
 
 fn is_admin() -> Result<(), String> {
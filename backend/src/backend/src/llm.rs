@@ -1,10 +1,17 @@
 use crate::models::{GitHubAnalysis, Repository};
-use ic_cdk::api::management_canister::http_request::{
-    http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod,
+use crate::outcall::{self, OutcallError};
+use crate::{
+    Memory, LLM_BUDGET_MEMORY_ID, LLM_CACHE_MEMORY_ID, LLM_CONSENSUS_MEMORY_ID, LLM_USAGE_MEMORY_ID,
+    MEMORY_MANAGER,
 };
+use ic_cdk::management_canister::{HttpHeader, HttpMethod, HttpRequestArgs};
+use ic_stable_structures::{StableBTreeMap, Storable};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use candid::CandidType;
+use candid::{CandidType, Principal};
+use ic_stable_structures::StableCell;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
 
 // LLM Analysis Results
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
@@ -19,6 +26,180 @@ pub struct LLMAnalysis {
     pub analysis_summary: String,
     pub strengths: Vec<String>,
     pub improvement_areas: Vec<String>,
+    // Present when the scores were aggregated from several samples
+    pub consensus: Option<LLMConsensus>,
+    // Model that produced the analysis; set once the samples are aggregated
+    pub model_version: Option<String>,
+}
+
+// Agreement between independent LLM samples for one score
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct ScoreAgreement {
+    pub metric: String,
+    pub median: f32,
+    pub variance: f32,
+    pub spread: f32, // max - min across samples
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct LLMConsensus {
+    pub requested_samples: u32,
+    pub sample_count: u32,
+    pub scores: Vec<ScoreAgreement>,
+}
+
+impl LLMConsensus {
+    /// True when at least two samples were taken and they agree on `metric` within `tolerance` points
+    pub fn agrees_on(&self, metric: &str, tolerance: f32) -> bool {
+        self.sample_count >= 2
+            && self.scores.iter()
+                .find(|s| s.metric == metric)
+                .is_some_and(|s| s.spread <= tolerance)
+    }
+}
+
+// Multi-sample scoring settings; `samples == 1` keeps the single-call behaviour
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct LLMConsensusConfig {
+    pub samples: u32,
+    pub tolerance: f32,
+}
+
+impl Default for LLMConsensusConfig {
+    fn default() -> Self {
+        Self { samples: 1, tolerance: 10.0 }
+    }
+}
+
+impl Storable for LLMConsensusConfig {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+const MAX_LLM_SAMPLES: u32 = 5;
+
+// Cached LLM result, keyed by model version and prompt fingerprint
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct CachedLLMAnalysis {
+    pub fingerprint: String,
+    pub model_version: String,
+    pub analysis: LLMAnalysis,
+    pub cached_at: u64,
+}
+
+impl Storable for CachedLLMAnalysis {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    // Unbounded since the model's free-text summary and suggestions have no fixed length
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+const LLM_MAX_TOKENS: u64 = 1000;
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+pub const USAGE_RETENTION_DAYS: u32 = 90; // longest window the usage report covers
+
+// Spend limits for LLM calls; `None` means unlimited
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct LLMBudgetConfig {
+    pub global_daily_cycles: Option<u128>,
+    pub global_daily_tokens: Option<u64>,
+    pub user_daily_cycles: Option<u128>,
+    pub user_daily_tokens: Option<u64>,
+    pub user_daily_calls: Option<u64>,
+}
+
+// Accumulated LLM usage for one day, either globally or for a single user
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct LLMUsage {
+    pub calls: u64,
+    pub failed_calls: u64,
+    pub cache_hits: u64,
+    pub budget_rejections: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    pub cycles_spent: u128,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct LLMUsageReport {
+    pub model_version: String,
+    pub budget: LLMBudgetConfig,
+    pub today: u64,
+    pub daily_usage: Vec<(u64, LLMUsage)>,
+    pub user_usage_today: Vec<(Principal, LLMUsage)>,
+}
+
+impl Storable for LLMBudgetConfig {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for LLMUsage {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Bounded {
+        max_size: 256,
+        is_fixed_size: false,
+    };
+}
+
+type LLMCacheStore = StableBTreeMap<String, CachedLLMAnalysis, Memory>;
+// Keys are "global:{day}" and "user:{day}:{principal}", days zero-padded so keys sort by day
+type LLMUsageStore = StableBTreeMap<String, LLMUsage, Memory>;
+
+thread_local! {
+    static LLM_CACHE: std::cell::RefCell<LLMCacheStore> = std::cell::RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(LLM_CACHE_MEMORY_ID)),
+        )
+    );
+
+    static LLM_BUDGET: std::cell::RefCell<StableCell<LLMBudgetConfig, Memory>> = std::cell::RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(LLM_BUDGET_MEMORY_ID)),
+            LLMBudgetConfig::default(),
+        ).expect("Failed to initialize LLM budget config")
+    );
+
+    static LLM_CONSENSUS: std::cell::RefCell<StableCell<LLMConsensusConfig, Memory>> = std::cell::RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(LLM_CONSENSUS_MEMORY_ID)),
+            LLMConsensusConfig::default(),
+        ).expect("Failed to initialize LLM consensus config")
+    );
+
+    static LLM_USAGE: std::cell::RefCell<LLMUsageStore> = std::cell::RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(LLM_USAGE_MEMORY_ID)),
+        )
+    );
 }
 
 // Azure OpenAI Configuration
@@ -58,30 +239,287 @@ pub fn set_llm_config(api_key: String, api_url: Option<String>) {
     AZURE_API_KEY.with(|key| *key.borrow_mut() = Some(api_key));
 }
 
-/// Analyze code quality using Azure OpenAI
-pub async fn analyze_code_quality(analysis: &GitHubAnalysis) -> Result<LLMAnalysis, String> {
-    // Check if Azure OpenAI is configured
-    if let Some(api_key) = AZURE_API_KEY.with(|key| key.borrow().clone()) {
-        let endpoint = AZURE_ENDPOINT.with(|ep| ep.borrow().clone());
-        if !endpoint.is_empty() {
-            match call_azure_openai_api(analysis, &api_key, &endpoint).await {
-                Ok(llm_result) => return Ok(llm_result),
-                Err(e) => {
-                    ic_cdk::println!("Azure OpenAI API call failed, using fallback analysis: {}", e);
-                    // Continue to fallback analysis
-                }
-            }
+/// Identifies the configured model, so cached results are invalidated when it changes
+pub fn model_version() -> String {
+    let deployment = AZURE_DEPLOYMENT.with(|d| d.borrow().clone());
+    let api_version = AZURE_API_VERSION.with(|v| v.borrow().clone());
+    format!("{}@{}", deployment, api_version)
+}
+
+/// SHA-256 fingerprint (hex) of the prompt sent to the LLM
+pub fn analysis_fingerprint(prompt: &str) -> String {
+    Sha256::digest(prompt.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn cache_key(model_version: &str, fingerprint: &str) -> String {
+    format!("{}:{}", model_version, fingerprint)
+}
+
+fn get_cached_analysis(model_version: &str, fingerprint: &str) -> Option<LLMAnalysis> {
+    LLM_CACHE.with(|cache| {
+        cache.borrow()
+            .get(&cache_key(model_version, fingerprint))
+            .map(|cached| cached.analysis)
+    })
+}
+
+fn cache_analysis(model_version: &str, fingerprint: &str, analysis: &LLMAnalysis) {
+    let entry = CachedLLMAnalysis {
+        fingerprint: fingerprint.to_string(),
+        model_version: model_version.to_string(),
+        analysis: analysis.clone(),
+        cached_at: ic_cdk::api::time(),
+    };
+
+    LLM_CACHE.with(|cache| {
+        cache.borrow_mut().insert(cache_key(model_version, fingerprint), entry);
+    });
+}
+
+/// Drop all cached LLM results, returning how many were removed
+pub fn clear_llm_cache() -> u64 {
+    LLM_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let keys: Vec<String> = cache.iter().map(|(key, _)| key).collect();
+        for key in &keys {
+            cache.remove(key);
+        }
+        keys.len() as u64
+    })
+}
+
+fn current_day() -> u64 {
+    ic_cdk::api::time() / NANOS_PER_DAY
+}
+
+fn global_usage_key(day: u64) -> String {
+    format!("global:{:06}", day)
+}
+
+fn user_usage_prefix(day: u64) -> String {
+    format!("user:{:06}:", day)
+}
+
+fn user_usage_key(user: Principal, day: u64) -> String {
+    format!("{}{}", user_usage_prefix(day), user.to_text())
+}
+
+fn get_usage(key: &str) -> LLMUsage {
+    LLM_USAGE.with(|usage| usage.borrow().get(&key.to_string()).unwrap_or_default())
+}
+
+/// Apply `update` to both today's global counters and the user's counters
+fn record_usage(user: Principal, update: impl Fn(&mut LLMUsage)) {
+    let day = current_day();
+    LLM_USAGE.with(|usage| {
+        let mut usage = usage.borrow_mut();
+        for key in [global_usage_key(day), user_usage_key(user, day)] {
+            let mut entry = usage.get(&key).unwrap_or_default();
+            update(&mut entry);
+            usage.insert(key, entry);
         }
+    });
+}
+
+pub fn get_llm_budget() -> LLMBudgetConfig {
+    LLM_BUDGET.with(|budget| budget.borrow().get().clone())
+}
+
+pub fn set_llm_budget(config: LLMBudgetConfig) -> Result<(), String> {
+    LLM_BUDGET.with(|budget| {
+        budget.borrow_mut().set(config)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to store LLM budget: {:?}", e))
+    })
+}
+
+pub fn get_llm_consensus_config() -> LLMConsensusConfig {
+    LLM_CONSENSUS.with(|config| config.borrow().get().clone())
+}
+
+pub fn set_llm_consensus_config(config: LLMConsensusConfig) -> Result<(), String> {
+    if config.samples == 0 || config.samples > MAX_LLM_SAMPLES {
+        return Err(format!("Sample count must be between 1 and {}", MAX_LLM_SAMPLES));
+    }
+    if !config.tolerance.is_finite() || config.tolerance < 0.0 {
+        return Err("Tolerance must be a non-negative number".to_string());
     }
 
-    // Fallback: Create analysis based on GitHub metrics
-    Ok(create_fallback_analysis(analysis))
+    LLM_CONSENSUS.with(|cell| {
+        cell.borrow_mut().set(config)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to store LLM consensus config: {:?}", e))
+    })
 }
 
-/// Call Azure OpenAI API for enhanced analysis
-async fn call_azure_openai_api(analysis: &GitHubAnalysis, api_key: &str, endpoint: &str) -> Result<LLMAnalysis, String> {
+/// Check whether another LLM call costing `call_cycles` fits into today's global and per-user budgets
+fn check_llm_budget(user: Principal, call_cycles: u128) -> Result<(), String> {
+    let budget = get_llm_budget();
+    let day = current_day();
+    let global = get_usage(&global_usage_key(day));
+    let per_user = get_usage(&user_usage_key(user, day));
+
+    if let Some(limit) = budget.global_daily_cycles {
+        if global.cycles_spent + call_cycles > limit {
+            return Err("Global daily LLM cycle budget exhausted".to_string());
+        }
+    }
+    if let Some(limit) = budget.global_daily_tokens {
+        if global.total_tokens >= limit {
+            return Err("Global daily LLM token budget exhausted".to_string());
+        }
+    }
+    if let Some(limit) = budget.user_daily_cycles {
+        if per_user.cycles_spent + call_cycles > limit {
+            return Err("Daily LLM cycle quota exhausted for this user".to_string());
+        }
+    }
+    if let Some(limit) = budget.user_daily_tokens {
+        if per_user.total_tokens >= limit {
+            return Err("Daily LLM token quota exhausted for this user".to_string());
+        }
+    }
+    if let Some(limit) = budget.user_daily_calls {
+        if per_user.calls >= limit {
+            return Err("Daily LLM call quota exhausted for this user".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// Usage for the last `days` days plus per-user usage for today
+pub fn get_llm_usage_report(days: u32) -> LLMUsageReport {
+    let today = current_day();
+    let first_day = today.saturating_sub(days.saturating_sub(1) as u64);
+
+    let daily_usage = (first_day..=today)
+        .map(|day| (day, get_usage(&global_usage_key(day))))
+        .collect();
+
+    let today_prefix = user_usage_prefix(today);
+    let mut user_usage_today: Vec<(Principal, LLMUsage)> = LLM_USAGE.with(|usage| {
+        usage.borrow()
+            .range(today_prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&today_prefix))
+            .filter_map(|(key, entry)| {
+                let principal = key.strip_prefix(&today_prefix)?;
+                Principal::from_text(principal).ok().map(|p| (p, entry))
+            })
+            .collect()
+    });
+    user_usage_today.sort_by_key(|(_, usage)| std::cmp::Reverse(usage.cycles_spent));
+
+    LLMUsageReport {
+        model_version: model_version(),
+        budget: get_llm_budget(),
+        today,
+        daily_usage,
+        user_usage_today,
+    }
+}
+
+/// Drop per-user counters from before today, which no quota reads anymore, and global counters
+/// older than the longest usage report. Returns the number of entries removed.
+pub fn prune_llm_usage() -> u64 {
+    let today = current_day();
+    let oldest_global = global_usage_key(today.saturating_sub(USAGE_RETENTION_DAYS as u64 - 1));
+    let stale_users = "user:".to_string()..user_usage_prefix(today);
+    let stale_globals = "global:".to_string()..oldest_global;
+
+    LLM_USAGE.with(|usage| {
+        let mut usage = usage.borrow_mut();
+        let keys: Vec<String> = usage.range(stale_users).map(|(key, _)| key)
+            .chain(usage.range(stale_globals).map(|(key, _)| key))
+            .collect();
+        for key in &keys {
+            usage.remove(key);
+        }
+        keys.len() as u64
+    })
+}
+
+/// Analyze code quality using Azure OpenAI
+pub async fn analyze_code_quality(user: Principal, analysis: &GitHubAnalysis) -> Result<LLMAnalysis, String> {
+    run_code_quality_analysis(user, analysis, true).await
+}
+
+/// Like `analyze_code_quality`, but ignores any cached result for this input
+pub async fn regenerate_code_quality(user: Principal, analysis: &GitHubAnalysis) -> Result<LLMAnalysis, String> {
+    run_code_quality_analysis(user, analysis, false).await
+}
+
+/// Fails when Azure OpenAI is not configured, the budget rejects the call or every sample fails,
+/// so callers never mistake a missing evaluation for an AI result
+async fn run_code_quality_analysis(user: Principal, analysis: &GitHubAnalysis, use_cache: bool) -> Result<LLMAnalysis, String> {
+    let api_key = AZURE_API_KEY.with(|key| key.borrow().clone())
+        .ok_or("Azure OpenAI is not configured")?;
+    let endpoint = AZURE_ENDPOINT.with(|ep| ep.borrow().clone());
+    if endpoint.is_empty() {
+        return Err("Azure OpenAI endpoint is not configured".to_string());
+    }
+
     let prompt = create_analysis_prompt(analysis);
+    let fingerprint = analysis_fingerprint(&prompt);
+    let samples = get_llm_consensus_config().samples.clamp(1, MAX_LLM_SAMPLES);
+    // Multi-sample results are aggregated differently, so they are cached separately
+    let model = if samples > 1 {
+        format!("{}#{}", model_version(), samples)
+    } else {
+        model_version()
+    };
+
+    // Reuse the previous result if neither the input nor the model changed
+    if let Some(cached) = use_cache.then(|| get_cached_analysis(&model, &fingerprint)).flatten() {
+        ic_cdk::println!("Using cached LLM analysis for {} ({})", analysis.username, fingerprint);
+        record_usage(user, |u| u.cache_hits += 1);
+        return Ok(cached);
+    }
 
+    let request = build_azure_openai_request(&prompt, &api_key, &endpoint);
+    let call_cycles = outcall::estimate_cost(&request);
+
+    let mut results = Vec::new();
+    let mut last_error = None;
+    for sample in 0..samples {
+        if let Err(e) = check_llm_budget(user, call_cycles) {
+            ic_cdk::println!("Skipping Azure OpenAI call for {}: {}", analysis.username, e);
+            record_usage(user, |u| u.budget_rejections += 1);
+            last_error = Some(e);
+            break;
+        }
+
+        match call_azure_openai_api(user, request.clone()).await {
+            Ok(llm_result) => results.push(llm_result),
+            Err(e) => {
+                ic_cdk::println!("Azure OpenAI sample {} of {} failed: {}", sample + 1, samples, e);
+                last_error = Some(e.to_string());
+            }
+        }
+    }
+
+    if results.is_empty() {
+        return Err(format!(
+            "Azure OpenAI analysis unavailable: {}",
+            last_error.unwrap_or_else(|| "no samples were taken".to_string())
+        ));
+    }
+
+    // A partial run is still usable now, but must not be served later as a full consensus
+    let complete = results.len() == samples as usize;
+    let mut llm_result = aggregate_samples(results, samples);
+    llm_result.model_version = Some(model.clone());
+    if complete {
+        cache_analysis(&model, &fingerprint, &llm_result);
+    }
+    Ok(llm_result)
+}
+
+/// Build the Azure OpenAI chat completion request for `prompt`
+fn build_azure_openai_request(prompt: &str, api_key: &str, endpoint: &str) -> HttpRequestArgs {
     let request_body = serde_json::json!({
         "messages": [
             {
@@ -93,7 +531,7 @@ async fn call_azure_openai_api(analysis: &GitHubAnalysis, api_key: &str, endpoin
                 "content": prompt
             }
         ],
-        "max_tokens": 1000,
+        "max_tokens": LLM_MAX_TOKENS,
         "temperature": 0.3,
         "stream": false
     });
@@ -107,13 +545,11 @@ async fn call_azure_openai_api(analysis: &GitHubAnalysis, api_key: &str, endpoin
         endpoint, deployment, api_version
     );
 
-    ic_cdk::println!("Calling Azure OpenAI at: {}", url);
-
-    let request = CanisterHttpRequestArgument {
+    HttpRequestArgs {
         url,
         method: HttpMethod::POST,
         body: Some(request_body.to_string().into_bytes()),
-        max_response_bytes: Some(4096),
+        max_response_bytes: Some(16384), // Starting limit; grows if a completion is truncated
         transform: None,
         headers: vec![
             HttpHeader {
@@ -125,26 +561,154 @@ async fn call_azure_openai_api(analysis: &GitHubAnalysis, api_key: &str, endpoin
                 value: "application/json".to_string(),
             },
         ],
+    }
+}
+
+/// Call Azure OpenAI API for enhanced analysis
+async fn call_azure_openai_api(user: Principal, request: HttpRequestArgs) -> Result<LLMAnalysis, OutcallError> {
+    ic_cdk::println!("Calling Azure OpenAI at: {}", request.url);
+
+    // A chat completion has no side effects, so a 429 or 5xx can safely be retried
+    let outcome = outcall::send_retryable("azure_openai", request).await;
+    let cycles_spent = outcome.cycles_spent;
+    let attempts = outcome.attempts as u64;
+
+    let body_str = match outcome.result.and_then(|response| response.text()) {
+        Ok(body_str) => body_str,
+        Err(e) => {
+            record_usage(user, |u| {
+                u.calls += attempts;
+                u.failed_calls += attempts;
+                u.cycles_spent += cycles_spent;
+            });
+            return Err(e);
+        }
     };
 
-    match http_request(request, 25_000_000_000).await {
-        Ok((response,)) => {
-            if response.status == 200u64 {
-                let body_str = String::from_utf8(response.body)
-                    .map_err(|e| format!("Failed to parse response body: {}", e))?;
-
-                ic_cdk::println!("Azure OpenAI response: {}", body_str);
-                parse_azure_openai_response(&body_str)
-            } else {
-                let error_body = String::from_utf8(response.body).unwrap_or_default();
-                Err(format!("Azure OpenAI API request failed with status: {} - {}", response.status, error_body))
+    ic_cdk::println!("Azure OpenAI response: {}", body_str);
+    let (prompt_tokens, completion_tokens, total_tokens) = parse_token_usage(&body_str);
+    record_usage(user, |u| {
+        u.calls += attempts;
+        u.failed_calls += attempts - 1;
+        u.prompt_tokens += prompt_tokens;
+        u.completion_tokens += completion_tokens;
+        u.total_tokens += total_tokens;
+        u.cycles_spent += cycles_spent;
+    });
+    parse_azure_openai_response(&body_str)
+}
+
+/// Combine independent samples: median scores, majority-voted lists, and text from the most typical sample
+fn aggregate_samples(mut samples: Vec<LLMAnalysis>, requested: u32) -> LLMAnalysis {
+    if requested <= 1 && samples.len() == 1 {
+        return samples.remove(0);
+    }
+
+    let metric = |name: &str, value: fn(&LLMAnalysis) -> f32| -> ScoreAgreement {
+        let values: Vec<f32> = samples.iter().map(value).collect();
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32;
+        let max = values.iter().cloned().fold(f32::MIN, f32::max);
+        let min = values.iter().cloned().fold(f32::MAX, f32::min);
+
+        ScoreAgreement {
+            metric: name.to_string(),
+            median: median(values),
+            variance,
+            spread: max - min,
+        }
+    };
+
+    let scores = vec![
+        metric("code_quality_score", |a| a.code_quality_score),
+        metric("contribution_consistency", |a| a.contribution_consistency),
+        metric("community_impact", |a| a.community_impact),
+        metric("technical_breadth", |a| a.technical_breadth),
+        metric("innovation_score", |a| a.innovation_score),
+    ];
+
+    let expertise_areas = majority_vote(samples.iter().map(|a| &a.expertise_areas), samples.len());
+    let recommended_badges = majority_vote(samples.iter().map(|a| &a.recommended_badges), samples.len());
+
+    // Take the narrative fields from the sample closest to the median quality score
+    let median_quality = scores[0].median;
+    let representative = samples.iter()
+        .min_by(|a, b| {
+            (a.code_quality_score - median_quality).abs()
+                .total_cmp(&(b.code_quality_score - median_quality).abs())
+        })
+        .cloned()
+        .unwrap_or_else(|| samples[0].clone());
+
+    LLMAnalysis {
+        code_quality_score: scores[0].median,
+        contribution_consistency: scores[1].median,
+        community_impact: scores[2].median,
+        technical_breadth: scores[3].median,
+        innovation_score: scores[4].median,
+        expertise_areas,
+        recommended_badges,
+        analysis_summary: representative.analysis_summary,
+        strengths: representative.strengths,
+        improvement_areas: representative.improvement_areas,
+        consensus: Some(LLMConsensus {
+            requested_samples: requested,
+            sample_count: samples.len() as u32,
+            scores,
+        }),
+        model_version: None,
+    }
+}
+
+fn median(mut values: Vec<f32>) -> f32 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+/// Entries (compared by canonical slug, so "C++" and "cpp" agree) that appear in more than half of the samples
+fn majority_vote<'a>(lists: impl Iterator<Item = &'a Vec<String>>, sample_count: usize) -> Vec<String> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+
+    for list in lists {
+        let mut seen = std::collections::HashSet::new();
+        for entry in list {
+            let key = crate::badge_ids::canonical_slug(entry);
+            if key.is_empty() || !seen.insert(key.clone()) {
+                continue;
+            }
+            match counts.iter_mut().find(|(existing, _)| crate::badge_ids::canonical_slug(existing) == key) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((entry.trim().to_string(), 1)),
             }
         }
-        Err((r, m)) => Err(format!("HTTP request failed: {:?} - {}", r, m)),
     }
+
+    counts.into_iter()
+        .filter(|(_, count)| *count * 2 > sample_count)
+        .map(|(entry, _)| entry)
+        .collect()
 }
 
-/// Create analysis prompt for LLM
+/// Extract (prompt, completion, total) token counts from the provider's `usage` block
+fn parse_token_usage(response: &str) -> (u64, u64, u64) {
+    let usage = serde_json::from_str::<Value>(response)
+        .map(|json| json["usage"].clone())
+        .unwrap_or(Value::Null);
+
+    (
+        usage["prompt_tokens"].as_u64().unwrap_or(0),
+        usage["completion_tokens"].as_u64().unwrap_or(0),
+        usage["total_tokens"].as_u64().unwrap_or(0),
+    )
+}
+
+/// Create analysis prompt for LLM. The prompt is also the cache fingerprint, so it only holds
+/// values that stay the same while the GitHub data does: account age is given in whole years.
 fn create_analysis_prompt(analysis: &GitHubAnalysis) -> String {
     format!(
         "Analyze this GitHub developer profile:
@@ -154,7 +718,7 @@ Total Repositories: {}
 Total Commits: {}
 Stars Received: {}
 Forks Received: {}
-Account Age: {} days
+Account Age: {} years
 Languages Used: {}
 Commit Frequency Score: {}
 Code Quality Score: {}
@@ -181,7 +745,7 @@ Format as JSON with these exact field names: code_quality_score, contribution_co
         analysis.total_commits,
         analysis.total_stars_received,
         analysis.total_forks_received,
-        analysis.account_age_days,
+        analysis.account_age_days / 365,
         analysis.languages.len(),
         analysis.commit_frequency_score,
         analysis.code_quality_score,
@@ -196,13 +760,18 @@ Format as JSON with these exact field names: code_quality_score, contribution_co
 }
 
 /// Parse Azure OpenAI API response
-fn parse_azure_openai_response(response: &str) -> Result<LLMAnalysis, String> {
+fn parse_azure_openai_response(response: &str) -> Result<LLMAnalysis, OutcallError> {
     let response_json: Value = serde_json::from_str(response)
-        .map_err(|e| format!("Failed to parse Azure OpenAI response JSON: {}", e))?;
+        .map_err(|e| OutcallError::InvalidResponse(format!("Failed to parse Azure OpenAI response JSON: {}", e)))?;
 
     let content = response_json["choices"][0]["message"]["content"]
         .as_str()
-        .ok_or("No content in Azure OpenAI response")?;
+        .ok_or_else(|| OutcallError::InvalidResponse("No content in Azure OpenAI response".to_string()))?;
+
+    // A completion cut off by the token limit is not valid JSON; reject it rather than scoring zeros
+    if response_json["choices"][0]["finish_reason"].as_str() == Some("length") {
+        return Err(OutcallError::InvalidResponse("Azure OpenAI completion truncated at the token limit".to_string()));
+    }
 
     // Try to parse as JSON first
     if let Ok(analysis_json) = serde_json::from_str::<Value>(content) {
@@ -240,6 +809,8 @@ fn parse_azure_openai_response(response: &str) -> Result<LLMAnalysis, String> {
                 .iter()
                 .filter_map(|v| v.as_str().map(|s| s.to_string()))
                 .collect(),
+            consensus: None,
+            model_version: None,
         })
     } else {
         // If not valid JSON, create a simple analysis from the text
@@ -254,105 +825,17 @@ fn parse_azure_openai_response(response: &str) -> Result<LLMAnalysis, String> {
             analysis_summary: content.chars().take(200).collect::<String>(),
             strengths: vec!["Active contributor".to_string()],
             improvement_areas: vec!["Expand skill diversity".to_string()],
+            consensus: None,
+            model_version: None,
         })
     }
 }
 
 /// Legacy parse function for backwards compatibility
-fn parse_llm_response(response: &str) -> Result<LLMAnalysis, String> {
+fn parse_llm_response(response: &str) -> Result<LLMAnalysis, OutcallError> {
     parse_azure_openai_response(response)
 }
 
-/// Create fallback analysis when LLM is not available
-fn create_fallback_analysis(analysis: &GitHubAnalysis) -> LLMAnalysis {
-    let mut expertise_areas = Vec::new();
-    let mut recommended_badges = Vec::new();
-    let mut strengths = Vec::new();
-    let mut improvement_areas = Vec::new();
-
-    // Determine expertise areas based on languages and activity
-    if !analysis.languages.is_empty() {
-        let top_language = analysis.languages
-            .iter()
-            .max_by_key(|(_, &usage)| usage)
-            .map(|(lang, _)| lang.clone())
-            .unwrap_or_default();
-
-        if !top_language.is_empty() {
-            expertise_areas.push(format!("{} Development", top_language));
-        }
-    }
-
-    if analysis.total_repos > 50 {
-        expertise_areas.push("Project Architecture".to_string());
-    }
-
-    if analysis.community_engagement_score > 70.0 {
-        expertise_areas.push("Open Source Leadership".to_string());
-    }
-
-    // Recommend badges based on metrics
-    if analysis.total_commits > 1000 {
-        recommended_badges.push("Commit Master".to_string());
-    }
-
-    if analysis.total_stars_received > 100 {
-        recommended_badges.push("Star Collector".to_string());
-    }
-
-    if analysis.languages.len() >= 5 {
-        recommended_badges.push("Polyglot".to_string());
-    }
-
-    // Determine strengths
-    if analysis.commit_frequency_score > 80.0 {
-        strengths.push("Consistent contribution pattern".to_string());
-    }
-
-    if analysis.code_quality_score > 75.0 {
-        strengths.push("High-quality code practices".to_string());
-    }
-
-    if analysis.total_repos > 20 {
-        strengths.push("Prolific project creator".to_string());
-    }
-
-    // Suggest improvements
-    if analysis.commit_frequency_score < 50.0 {
-        improvement_areas.push("Increase contribution consistency".to_string());
-    }
-
-    if analysis.community_engagement_score < 30.0 {
-        improvement_areas.push("Engage more with the community".to_string());
-    }
-
-    if analysis.languages.len() < 3 {
-        improvement_areas.push("Explore additional programming languages".to_string());
-    }
-
-    let analysis_summary = format!(
-        "Developer with {} repositories and {} commits. Shows {} in {} languages with {} community engagement.",
-        analysis.total_repos,
-        analysis.total_commits,
-        if analysis.code_quality_score > 70.0 { "strong" } else { "moderate" },
-        analysis.languages.len(),
-        if analysis.community_engagement_score > 50.0 { "good" } else { "limited" }
-    );
-
-    LLMAnalysis {
-        code_quality_score: analysis.code_quality_score,
-        contribution_consistency: analysis.commit_frequency_score,
-        community_impact: analysis.community_engagement_score,
-        technical_breadth: (analysis.languages.len() as f32 * 10.0).min(100.0),
-        innovation_score: (analysis.total_stars_received as f32 / 10.0).min(100.0),
-        expertise_areas,
-        recommended_badges,
-        analysis_summary,
-        strengths,
-        improvement_areas,
-    }
-}
-
 /// Enhanced repository analysis
 pub async fn analyze_repository_quality(repos: &[Repository]) -> Result<f32, String> {
     let mut quality_score = 0.0;
@@ -416,4 +899,100 @@ pub async fn analyze_contribution_patterns(analysis: &GitHubAnalysis) -> Result<
     pattern_score += diversity_score * 0.3;
 
     Ok(pattern_score.min(100.0))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(quality: f32, impact: f32, expertise: &[&str], summary: &str) -> LLMAnalysis {
+        LLMAnalysis {
+            code_quality_score: quality,
+            contribution_consistency: 50.0,
+            community_impact: impact,
+            technical_breadth: 50.0,
+            innovation_score: 50.0,
+            expertise_areas: expertise.iter().map(|e| e.to_string()).collect(),
+            recommended_badges: Vec::new(),
+            analysis_summary: summary.to_string(),
+            strengths: Vec::new(),
+            improvement_areas: Vec::new(),
+            consensus: None,
+            model_version: None,
+        }
+    }
+
+    fn consensus(sample_count: u32, spread: f32) -> LLMConsensus {
+        LLMConsensus {
+            requested_samples: sample_count,
+            sample_count,
+            scores: vec![ScoreAgreement { metric: "code_quality_score".to_string(), median: 80.0, variance: 0.0, spread }],
+        }
+    }
+
+    #[test]
+    fn median_of_odd_and_even_counts() {
+        assert_eq!(median(vec![3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(vec![4.0, 1.0, 3.0, 2.0]), 2.5);
+        assert_eq!(median(vec![7.0]), 7.0);
+    }
+
+    #[test]
+    fn samples_are_aggregated_to_medians_with_spread_and_variance() {
+        let samples = vec![
+            sample(70.0, 40.0, &[], "low"),
+            sample(90.0, 60.0, &[], "high"),
+            sample(80.0, 50.0, &[], "middle"),
+        ];
+
+        let aggregated = aggregate_samples(samples, 3);
+
+        assert_eq!(aggregated.code_quality_score, 80.0);
+        assert_eq!(aggregated.community_impact, 50.0);
+        assert_eq!(aggregated.analysis_summary, "middle");
+
+        let consensus = aggregated.consensus.unwrap();
+        assert_eq!(consensus.sample_count, 3);
+        let quality = &consensus.scores[0];
+        assert_eq!(quality.metric, "code_quality_score");
+        assert_eq!(quality.spread, 20.0);
+        assert!((quality.variance - 200.0 / 3.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn single_sample_is_returned_without_consensus() {
+        let aggregated = aggregate_samples(vec![sample(70.0, 40.0, &["Rust"], "only")], 1);
+        assert!(aggregated.consensus.is_none());
+        assert_eq!(aggregated.code_quality_score, 70.0);
+    }
+
+    #[test]
+    fn partial_run_still_reports_how_many_samples_answered() {
+        let aggregated = aggregate_samples(vec![sample(70.0, 40.0, &[], "only")], 3);
+        let consensus = aggregated.consensus.unwrap();
+        assert_eq!((consensus.requested_samples, consensus.sample_count), (3, 1));
+        assert!(!consensus.agrees_on("code_quality_score", 100.0));
+    }
+
+    #[test]
+    fn majority_vote_keeps_entries_most_samples_share() {
+        let lists = [
+            vec!["C++".to_string(), "Rust".to_string(), "rust".to_string()],
+            vec!["cpp".to_string(), "Go".to_string()],
+            vec!["Rust".to_string()],
+        ];
+        assert_eq!(majority_vote(lists.iter(), 3), vec!["C++".to_string(), "Rust".to_string()]);
+
+        // Repeating an entry within one sample does not count twice
+        let lists = [vec!["Go".to_string(), "go".to_string()], Vec::new(), Vec::new()];
+        assert!(majority_vote(lists.iter(), 3).is_empty());
+    }
+
+    #[test]
+    fn agreement_needs_two_samples_within_tolerance() {
+        assert!(!consensus(1, 0.0).agrees_on("code_quality_score", 10.0));
+        assert!(consensus(2, 5.0).agrees_on("code_quality_score", 10.0));
+        assert!(consensus(3, 10.0).agrees_on("code_quality_score", 10.0));
+        assert!(!consensus(3, 15.0).agrees_on("code_quality_score", 10.0));
+        assert!(!consensus(3, 0.0).agrees_on("innovation_score", 10.0));
+    }
+}
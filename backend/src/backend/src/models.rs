@@ -13,9 +13,46 @@ pub struct UserProfile {
     pub created_at: u64,
     pub updated_at: u64,
     pub last_github_sync: Option<u64>,
-    pub reputation_score: u64,
+    pub reputation_score: u64,          // equals current_reputation once computed
     pub badges: Vec<Badge>,
     pub total_badges: u32,
+    pub revoked_badges: Option<Vec<RevokedBadge>>,
+    pub lifetime_reputation: Option<u64>, // sum of tier points of all held badges
+    pub current_reputation: Option<u64>,  // lifetime reputation with time decay applied, see `ReputationConfig`
+    pub reputation_breakdown: Option<ReputationBreakdown>,
+}
+
+// Where current reputation comes from; the category scores add up to it
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct ReputationBreakdown {
+    pub language: u64,
+    pub contribution: u64,
+    pub achievement: u64,
+    pub special: u64,
+    pub languages: Vec<LanguageScore>, // highest first
+    pub computed_at: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, CandidType, Serialize, Deserialize)]
+pub struct LanguageScore {
+    pub language: String, // canonical slug, as in "lang_{language}" badge ids
+    pub name: String,
+    pub score: u64,
+}
+
+impl ReputationBreakdown {
+    pub fn total(&self) -> u64 {
+        self.language + self.contribution + self.achievement + self.special
+    }
+
+    /// Whether both breakdowns hold the same scores, whenever they were computed
+    pub fn same_scores(&self, other: &ReputationBreakdown) -> bool {
+        self.language == other.language
+            && self.contribution == other.contribution
+            && self.achievement == other.achievement
+            && self.special == other.special
+            && self.languages == other.languages
+    }
 }
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
@@ -77,9 +114,110 @@ pub struct Badge {
     pub earned_at: u64,
     pub criteria_met: Vec<String>,
     pub score_achieved: u32,
+    pub upgraded_at: Option<u64>,
+    pub tier_history: Option<Vec<TierChange>>, // every tier reached, oldest first
+    pub nft_token_id: Option<u64>,
+    pub at_risk_since: Option<u64>, // first sync at which the badge no longer qualified
+    pub season: Option<String>,     // id of the season a seasonal badge was earned in
+    pub rule_version: Option<u32>,  // badge rule set version the badge was last confirmed under
+    pub last_confirmed_at: Option<u64>, // analysis time of the last evaluation that still earned it
+    pub evidence: Option<BadgeEvidence>,
     pub metadata: BadgeMetadata,
 }
 
+// Why a badge was granted: the values it was evaluated on and the analysis they came from
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BadgeEvidence {
+    pub inputs: Vec<EvidenceValue>,
+    pub rule_version: u32,
+    pub analyzed_at: u64,
+    pub analysis_hash: String, // SHA-256 of the snapshot's canonical JSON, see `get_badge_evidence`
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct EvidenceValue {
+    pub name: String,
+    pub value: String,
+}
+
+impl BadgeEvidence {
+    /// Evidence with only the evaluated inputs; the pipeline stamps the analysis and rule version
+    pub fn from_inputs(inputs: &[(&str, String)]) -> Self {
+        Self {
+            inputs: inputs.iter()
+                .map(|(name, value)| EvidenceValue { name: name.to_string(), value: value.clone() })
+                .collect(),
+            rule_version: 0,
+            analyzed_at: 0,
+            analysis_hash: String::new(),
+        }
+    }
+}
+
+// A held badge together with the analysis snapshot its evidence points to
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BadgeEvidenceReport {
+    pub badge: Badge,
+    pub snapshot: Option<GitHubAnalysis>,
+}
+
+// How far an analysis is from the next tier of a badge
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct TierProgress {
+    pub badge_id: String,
+    pub name: String,
+    pub current_tier: Option<BadgeTier>, // None while the badge is still locked
+    pub current_value: u32,
+    pub next_tier: Option<BadgeTier>,    // None once the top tier is reached
+    pub next_threshold: Option<u32>,
+    pub percent: f32,                    // share of the next threshold reached, 100 at the top tier
+}
+
+// Hypothetical changes applied on top of an analysis before simulating badges
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct AnalysisDeltas {
+    pub total_repos: Option<i64>,
+    pub total_commits: Option<i64>,
+    pub total_stars_received: Option<i64>,
+    pub total_forks_received: Option<i64>,
+    pub followers: Option<i64>,
+    pub account_age_days: Option<i64>,
+    pub commit_frequency_score: Option<f32>,
+    pub code_quality_score: Option<f32>,
+    pub community_engagement_score: Option<f32>,
+    pub language_volume: Vec<(String, u32)>, // extra code per language, in repository size units
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BadgeSimulationRequest {
+    pub analysis: Option<GitHubAnalysis>, // defaults to the caller's cached analysis
+    pub deltas: Option<AnalysisDeltas>,
+}
+
+// Badges an analysis would produce, without AI badges, which need the LLM step
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BadgeSimulation {
+    pub badges: Vec<Badge>,
+    pub progress: Vec<TierProgress>,
+    pub reputation_score: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct TierChange {
+    pub tier: BadgeTier,
+    pub score_achieved: u32,
+    pub changed_at: u64,
+    pub reason: Option<String>, // set for downgrades
+}
+
+// A badge taken away during reconciliation, kept for the user's record
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct RevokedBadge {
+    pub badge: Badge,
+    pub reason: String,
+    pub revoked_at: u64,
+}
+
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub enum BadgeCategory {
     Language(String),      // "Rust", "Python", "JavaScript", etc.
@@ -88,8 +226,8 @@ pub enum BadgeCategory {
     Special(String),       // "EarlyAdopter", "Mentor", "Innovation", etc.
 }
 
-// Updated Badge Tier System: Bronze/Silver/Gold with levels 1-3
-#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+// Updated Badge Tier System: Bronze/Silver/Gold with levels 1-3, declared lowest to highest
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, CandidType, Serialize, Deserialize)]
 pub enum BadgeTier {
     Bronze1,
     Bronze2,
@@ -183,6 +321,20 @@ pub struct LLMAnalysis {
     pub improvement_areas: Vec<String>,
 }
 
+// Latest AI-generated insights for a user, as shown on the profile
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct AIInsights {
+    pub user_principal: Principal,
+    pub analysis_summary: String,
+    pub strengths: Vec<String>,
+    pub improvement_areas: Vec<String>,
+    pub recommended_badges: Vec<String>,
+    pub expertise_areas: Vec<String>,
+    pub model_version: Option<String>, // None when produced by the metric-based fallback
+    pub generated_at: u64,
+    pub hidden: bool,
+}
+
 // Admin and Analytics
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct ProfileStats {
@@ -243,10 +395,8 @@ impl Storable for UserProfile {
         candid::decode_one(&bytes).unwrap()
     }
 
-    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Bounded {
-        max_size: 8192, // Increased for comprehensive profile data
-        is_fixed_size: false,
-    };
+    // Unbounded since badges keep their tier history; existing bounded data migrates transparently
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
 }
 
 impl Storable for GitHubAnalysis {
@@ -258,8 +408,18 @@ impl Storable for GitHubAnalysis {
         candid::decode_one(&bytes).unwrap()
     }
 
-    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Bounded {
-        max_size: 4096,
-        is_fixed_size: false,
-    };
-}
\ No newline at end of file
+    // Unbounded since analyses include the full repository list and are kept as evidence snapshots
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+impl Storable for AIInsights {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    // Unbounded since insights carry the model's free-text output
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
@@ -0,0 +1,588 @@
+use crate::{Memory, MEMORY_MANAGER, OUTCALL_POLICY_MEMORY_ID};
+use candid::CandidType;
+use ic_cdk::api::time;
+use ic_cdk::call::{Error as CallError, RejectCode};
+use ic_cdk::management_canister::{cost_http_request, http_request, HttpMethod, HttpRequestArgs, HttpRequestResult};
+use ic_stable_structures::{StableCell, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+// Retry and circuit-breaker settings shared by all outbound HTTP calls
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct OutcallPolicy {
+    pub max_retries: u32, // GET and HEAD requests, and POSTs sent with `send_retryable`
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub breaker_failure_threshold: u32, // consecutive failures before a host is cut off
+    pub breaker_cooldown_secs: u64,
+    pub max_response_bytes_cap: Option<u64>, // None allows up to the IC limit of 2MB
+}
+
+impl Default for OutcallPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff_ms: 1_000,
+            max_backoff_ms: 8_000,
+            breaker_failure_threshold: 5,
+            breaker_cooldown_secs: 300,
+            max_response_bytes_cap: None,
+        }
+    }
+}
+
+impl Storable for OutcallPolicy {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub enum CircuitState {
+    #[default]
+    Closed,
+    Open { until: u64 },
+    HalfOpen, // a single trial call is let through; its failure re-opens immediately
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct CircuitStatus {
+    pub host: String,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+}
+
+// Response size and cost figures for one logical endpoint (e.g. "github_repos")
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct EndpointMetrics {
+    pub endpoint: String,
+    pub requests: u64,
+    pub truncated_responses: u64,
+    pub resized_retries: u64,
+    pub last_response_bytes: u64,
+    pub current_max_response_bytes: u64,
+    pub wasted_cycles: u128, // spent on attempts that had to be repeated with a larger limit
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct OutcallStatus {
+    pub policy: OutcallPolicy,
+    pub circuits: Vec<CircuitStatus>,
+    pub endpoints: Vec<EndpointMetrics>,
+}
+
+// Largest response an HTTP outcall may return
+const IC_MAX_RESPONSE_BYTES: u64 = 2_000_000;
+
+// A half-open trial still unresolved after this long is assumed lost, e.g. to a trap
+const PROBE_TIMEOUT_NS: u64 = 5 * 60 * 1_000_000_000;
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum OutcallError {
+    NotConfigured(String),
+    CircuitOpen { host: String, until: u64 },
+    Rejected { code: u32, message: String },
+    RateLimited { status: u16 },
+    ServerError { status: u16, body: String },
+    HttpStatus { status: u16, body: String },
+    Truncated { limit: u64 }, // response did not fit even at the configured cap
+    InvalidResponse(String),
+}
+
+impl OutcallError {
+    /// Transient failures worth retrying after a backoff
+    fn is_retryable(&self) -> bool {
+        match self {
+            OutcallError::RateLimited { .. } | OutcallError::ServerError { .. } => true,
+            OutcallError::Rejected { code, .. } => *code == RejectCode::SysTransient as u32,
+            _ => false,
+        }
+    }
+
+    /// Failures that indicate the remote host is unhealthy and count towards its circuit breaker
+    fn is_host_failure(&self) -> bool {
+        matches!(
+            self,
+            OutcallError::RateLimited { .. } | OutcallError::ServerError { .. } | OutcallError::Rejected { .. }
+        )
+    }
+}
+
+impl fmt::Display for OutcallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutcallError::NotConfigured(what) => write!(f, "{} not configured", what),
+            OutcallError::CircuitOpen { host, until } => {
+                write!(f, "Circuit open for {}, calls suspended until {}", host, until)
+            }
+            OutcallError::Rejected { code, message } => {
+                write!(f, "HTTP request failed: reject code {} - {}", code, message)
+            }
+            OutcallError::RateLimited { status } => write!(f, "Rate limited with status: {}", status),
+            OutcallError::ServerError { status, body } => {
+                write!(f, "Server error with status: {} - {}", status, body)
+            }
+            OutcallError::HttpStatus { status, body } => {
+                write!(f, "Request failed with status: {} - {}", status, body)
+            }
+            OutcallError::Truncated { limit } => {
+                write!(f, "Response exceeded the maximum size of {} bytes", limit)
+            }
+            OutcallError::InvalidResponse(message) => write!(f, "Invalid response: {}", message),
+        }
+    }
+}
+
+pub struct OutcallResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+    pub content_length: Option<u64>,
+}
+
+impl OutcallResponse {
+    pub fn text(self) -> Result<String, OutcallError> {
+        String::from_utf8(self.body)
+            .map_err(|e| OutcallError::InvalidResponse(format!("Failed to parse response body: {}", e)))
+    }
+}
+
+// Result of `send` together with what it cost, for callers that account for spend
+pub struct OutcallOutcome {
+    pub result: Result<OutcallResponse, OutcallError>,
+    pub attempts: u32,
+    pub cycles_spent: u128,
+}
+
+#[derive(Default)]
+struct Circuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    probe_started_at: Option<u64>, // set while the half-open trial call is in flight
+}
+
+thread_local! {
+    static OUTCALL_POLICY: RefCell<StableCell<OutcallPolicy, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(OUTCALL_POLICY_MEMORY_ID)),
+            OutcallPolicy::default(),
+        ).expect("Failed to initialize outcall policy")
+    );
+
+    // Breaker state is runtime-only and starts closed after an upgrade
+    static CIRCUITS: RefCell<HashMap<String, Circuit>> = RefCell::new(HashMap::new());
+
+    static ENDPOINT_METRICS: RefCell<HashMap<String, EndpointMetrics>> = RefCell::new(HashMap::new());
+}
+
+pub fn get_outcall_policy() -> OutcallPolicy {
+    OUTCALL_POLICY.with(|policy| policy.borrow().get().clone())
+}
+
+pub fn set_outcall_policy(policy: OutcallPolicy) -> Result<(), String> {
+    if policy.max_retries > 5 {
+        return Err("At most 5 retries are allowed".to_string());
+    }
+    if policy.initial_backoff_ms == 0 || policy.initial_backoff_ms > policy.max_backoff_ms {
+        return Err("Initial backoff must be positive and not exceed the maximum backoff".to_string());
+    }
+    if policy.breaker_failure_threshold == 0 {
+        return Err("Breaker failure threshold must be at least 1".to_string());
+    }
+    if policy.max_response_bytes_cap.is_some_and(|cap| cap == 0 || cap > IC_MAX_RESPONSE_BYTES) {
+        return Err(format!("Response size cap must be between 1 and {} bytes", IC_MAX_RESPONSE_BYTES));
+    }
+
+    OUTCALL_POLICY.with(|cell| {
+        cell.borrow_mut().set(policy)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to store outcall policy: {:?}", e))
+    })
+}
+
+pub fn get_outcall_status() -> OutcallStatus {
+    let circuits = CIRCUITS.with(|circuits| {
+        circuits.borrow().iter()
+            .map(|(host, circuit)| CircuitStatus {
+                host: host.clone(),
+                state: circuit.state.clone(),
+                consecutive_failures: circuit.consecutive_failures,
+            })
+            .collect()
+    });
+
+    let mut endpoints: Vec<EndpointMetrics> = ENDPOINT_METRICS.with(|metrics| {
+        metrics.borrow().values().cloned().collect()
+    });
+    endpoints.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+
+    OutcallStatus {
+        policy: get_outcall_policy(),
+        circuits,
+        endpoints,
+    }
+}
+
+pub fn reset_circuits() {
+    CIRCUITS.with(|circuits| circuits.borrow_mut().clear());
+}
+
+/// Cycles charged for a single attempt of `request`
+pub fn estimate_cost(request: &HttpRequestArgs) -> u128 {
+    cost_http_request(request)
+}
+
+/// Send an HTTP outcall with a per-host circuit breaker. Idempotent (GET and HEAD) requests are
+/// retried on 5xx/429/transient rejects with exponential backoff; other requests, such as the
+/// OAuth token exchange, are sent exactly once. Use `send_retryable` for POST endpoints that are
+/// safe to repeat.
+///
+/// `endpoint` names the logical API being called. The request's `max_response_bytes` is the
+/// endpoint's default; the limit actually used adapts to previously observed response sizes,
+/// and truncated responses of requests that may be retried are sent again with a larger limit
+/// up to the configured cap. Truncations also raise the limit later calls start with.
+pub async fn send(endpoint: &str, request: HttpRequestArgs) -> OutcallOutcome {
+    send_with_retries(endpoint, request, false).await
+}
+
+/// `send` for a request that may be repeated regardless of its method, e.g. a chat completion
+pub async fn send_retryable(endpoint: &str, request: HttpRequestArgs) -> OutcallOutcome {
+    send_with_retries(endpoint, request, true).await
+}
+
+async fn send_with_retries(endpoint: &str, mut request: HttpRequestArgs, retryable: bool) -> OutcallOutcome {
+    let policy = get_outcall_policy();
+    let host = host_of(&request.url);
+    let cap = policy.max_response_bytes_cap.unwrap_or(IC_MAX_RESPONSE_BYTES);
+    let idempotent = matches!(request.method, HttpMethod::GET | HttpMethod::HEAD);
+    let may_retry = retryable || idempotent;
+    let mut attempts = 0;
+    let mut cycles_spent = 0;
+    let mut holds_probe = false;
+
+    request.max_response_bytes = Some(initial_response_limit(endpoint, request.max_response_bytes, cap));
+
+    loop {
+        // The half-open trial keeps its slot across its own retries
+        if !holds_probe {
+            match check_circuit(&host) {
+                Ok(probe) => holds_probe = probe,
+                Err(e) => return OutcallOutcome { result: Err(e), attempts, cycles_spent },
+            }
+        }
+
+        attempts += 1;
+        let attempt_cycles = cost_http_request(&request);
+        cycles_spent += attempt_cycles;
+
+        let result = match http_request(&request).await {
+            Ok(response) => classify_response(response),
+            Err(e) => Err(reject_error(e)),
+        };
+
+        let limit = request.max_response_bytes.unwrap_or(cap);
+        if let Some(reported_bytes) = truncation(&result) {
+            record_truncation(endpoint, attempt_cycles, reported_bytes.max(limit));
+
+            if limit < cap && may_retry {
+                let new_limit = reported_bytes.max(limit.saturating_mul(2)).min(cap);
+                request.max_response_bytes = Some(new_limit);
+                let extra_cycles = cost_http_request(&request).saturating_sub(attempt_cycles);
+
+                ic_cdk::println!(
+                    "Response from {} ({}) truncated at {} bytes, retrying with {} bytes (+{} cycles per attempt)",
+                    endpoint, host, limit, new_limit, extra_cycles
+                );
+                record_resize(endpoint, new_limit);
+                continue;
+            }
+
+            ic_cdk::println!("Response from {} ({}) exceeds the {} byte limit", endpoint, host, limit);
+            if holds_probe {
+                release_probe(&host);
+            }
+            return OutcallOutcome {
+                result: Err(OutcallError::Truncated { limit }),
+                attempts,
+                cycles_spent,
+            };
+        }
+
+        match &result {
+            Err(e) if e.is_host_failure() => record_failure(&host, &policy),
+            _ => record_success(&host),
+        }
+
+        if let Ok(response) = &result {
+            record_response_size(endpoint, response.body.len() as u64, limit);
+        }
+
+        // A failed trial re-opened the circuit, so the next attempt has to pass the check again
+        holds_probe = false;
+
+        match result {
+            Err(e) if may_retry && e.is_retryable() && attempts <= policy.max_retries => {
+                let delay_ms = backoff_delay_ms(&policy, attempts);
+                ic_cdk::println!("Outcall to {} failed (attempt {}): {}, retrying in {}ms", host, attempts, e, delay_ms);
+                sleep(Duration::from_millis(delay_ms)).await;
+            }
+            result => return OutcallOutcome { result, attempts, cycles_spent },
+        }
+    }
+}
+
+fn backoff_delay_ms(policy: &OutcallPolicy, attempt: u32) -> u64 {
+    policy.initial_backoff_ms
+        .saturating_mul(1u64 << (attempt - 1).min(16))
+        .min(policy.max_backoff_ms)
+}
+
+#[derive(Default)]
+struct SleepState {
+    elapsed: bool,
+    waker: Option<Waker>,
+}
+
+// Future resolved by a one-shot timer, so waiting costs no instructions
+struct Sleep(Rc<RefCell<SleepState>>);
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.0.borrow_mut();
+        if state.elapsed {
+            return Poll::Ready(());
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+fn sleep(delay: Duration) -> Sleep {
+    let state = Rc::new(RefCell::new(SleepState::default()));
+    let timer_state = Rc::clone(&state);
+    ic_cdk_timers::set_timer(delay, move || {
+        // Release the borrow first: the executor polls the woken task right away
+        let waker = {
+            let mut state = timer_state.borrow_mut();
+            state.elapsed = true;
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    });
+    Sleep(state)
+}
+
+/// Limit for the first attempt: the endpoint default, raised to fit the last observed response
+/// or, after a truncation, the size it was known to need
+fn initial_response_limit(endpoint: &str, default: Option<u64>, cap: u64) -> u64 {
+    let default = default.unwrap_or(cap);
+    let observed = ENDPOINT_METRICS.with(|metrics| {
+        metrics.borrow().get(endpoint).map(|m| m.last_response_bytes).unwrap_or(0)
+    });
+
+    // Leave 25% headroom over the last response so slowly growing payloads still fit
+    default.max(observed.saturating_add(observed / 4)).min(cap)
+}
+
+/// Detect a response that did not fit the size limit, returning the size the server reported if known.
+///
+/// The IC rejects an oversized response with `SysFatal`, the same code it uses for other permanent
+/// outcall failures such as an invalid URL, so the code alone cannot identify it; the message is
+/// only inspected for rejects carrying that code.
+fn truncation(result: &Result<OutcallResponse, OutcallError>) -> Option<u64> {
+    match result {
+        Err(OutcallError::Rejected { code, message }) if *code == RejectCode::SysFatal as u32 => {
+            let message = message.to_lowercase();
+            let size_limit_hit = message.contains("size limit")
+                || message.contains("exceeds")
+                || message.contains("too large");
+            size_limit_hit.then_some(0)
+        }
+        Ok(response) => {
+            let body_len = response.body.len() as u64;
+            match response.content_length {
+                Some(expected) if expected > body_len => Some(expected),
+                _ => None,
+            }
+        }
+        Err(_) => None,
+    }
+}
+
+fn with_endpoint_metrics(endpoint: &str, update: impl FnOnce(&mut EndpointMetrics)) {
+    ENDPOINT_METRICS.with(|metrics| {
+        let mut metrics = metrics.borrow_mut();
+        let entry = metrics.entry(endpoint.to_string()).or_insert_with(|| EndpointMetrics {
+            endpoint: endpoint.to_string(),
+            ..Default::default()
+        });
+        update(entry);
+    });
+}
+
+/// Count a truncated attempt and remember that the endpoint needs at least `needed_bytes`
+fn record_truncation(endpoint: &str, wasted_cycles: u128, needed_bytes: u64) {
+    with_endpoint_metrics(endpoint, |m| {
+        m.requests += 1;
+        m.truncated_responses += 1;
+        m.wasted_cycles += wasted_cycles;
+        m.last_response_bytes = m.last_response_bytes.max(needed_bytes);
+    });
+}
+
+fn record_resize(endpoint: &str, new_limit: u64) {
+    with_endpoint_metrics(endpoint, |m| {
+        m.resized_retries += 1;
+        m.current_max_response_bytes = new_limit;
+    });
+}
+
+fn record_response_size(endpoint: &str, bytes: u64, limit: u64) {
+    with_endpoint_metrics(endpoint, |m| {
+        m.requests += 1;
+        m.last_response_bytes = bytes;
+        m.current_max_response_bytes = limit;
+    });
+}
+
+fn classify_response(response: HttpRequestResult) -> Result<OutcallResponse, OutcallError> {
+    let status = u16::try_from(&response.status.0).unwrap_or(u16::MAX);
+    let content_length = response.headers.iter()
+        .find(|h| h.name.eq_ignore_ascii_case("content-length"))
+        .and_then(|h| h.value.trim().parse().ok());
+
+    match status {
+        200..=299 => Ok(OutcallResponse { status, body: response.body, content_length }),
+        429 => Err(OutcallError::RateLimited { status }),
+        500..=599 => Err(OutcallError::ServerError {
+            status,
+            body: String::from_utf8_lossy(&response.body).into_owned(),
+        }),
+        _ => Err(OutcallError::HttpStatus {
+            status,
+            body: String::from_utf8_lossy(&response.body).into_owned(),
+        }),
+    }
+}
+
+fn reject_error(error: CallError) -> OutcallError {
+    match error {
+        CallError::CallRejected(rejected) => OutcallError::Rejected {
+            code: rejected.raw_reject_code(),
+            message: rejected.reject_message().to_string(),
+        },
+        other => OutcallError::Rejected {
+            code: RejectCode::SysFatal as u32,
+            message: other.to_string(),
+        },
+    }
+}
+
+fn host_of(url: &str) -> String {
+    let without_scheme = url.split("://").nth(1).unwrap_or(url);
+    without_scheme
+        .split(['/', '?'])
+        .next()
+        .unwrap_or(without_scheme)
+        .to_lowercase()
+}
+
+/// Whether a call to `host` may go out, and if so whether it is the half-open trial
+fn check_circuit(host: &str) -> Result<bool, OutcallError> {
+    CIRCUITS.with(|circuits| {
+        let mut circuits = circuits.borrow_mut();
+        let Some(circuit) = circuits.get_mut(host) else {
+            return Ok(false);
+        };
+
+        let now = time();
+        match circuit.state {
+            CircuitState::Open { until } if now < until => Err(OutcallError::CircuitOpen {
+                host: host.to_string(),
+                until,
+            }),
+            CircuitState::Closed => Ok(false),
+            // Cooldown passed (the timer may not have fired yet): let exactly one trial through
+            CircuitState::Open { .. } | CircuitState::HalfOpen => {
+                circuit.state = CircuitState::HalfOpen;
+                match circuit.probe_started_at {
+                    Some(started) if now.saturating_sub(started) < PROBE_TIMEOUT_NS => Err(OutcallError::CircuitOpen {
+                        host: host.to_string(),
+                        until: started.saturating_add(PROBE_TIMEOUT_NS),
+                    }),
+                    _ => {
+                        circuit.probe_started_at = Some(now);
+                        Ok(true)
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Free the half-open trial slot after a trial that said nothing about the host's health
+fn release_probe(host: &str) {
+    CIRCUITS.with(|circuits| {
+        if let Some(circuit) = circuits.borrow_mut().get_mut(host) {
+            circuit.probe_started_at = None;
+        }
+    });
+}
+
+fn record_success(host: &str) {
+    CIRCUITS.with(|circuits| {
+        circuits.borrow_mut().remove(host);
+    });
+}
+
+fn record_failure(host: &str, policy: &OutcallPolicy) {
+    let opened = CIRCUITS.with(|circuits| {
+        let mut circuits = circuits.borrow_mut();
+        let circuit = circuits.entry(host.to_string()).or_default();
+        circuit.consecutive_failures += 1;
+
+        let trial_failed = matches!(circuit.state, CircuitState::HalfOpen);
+        circuit.probe_started_at = None;
+        if trial_failed || circuit.consecutive_failures >= policy.breaker_failure_threshold {
+            let cooldown_ns = policy.breaker_cooldown_secs.saturating_mul(1_000_000_000);
+            circuit.state = CircuitState::Open { until: time().saturating_add(cooldown_ns) };
+            true
+        } else {
+            false
+        }
+    });
+
+    if opened {
+        ic_cdk::println!("Circuit opened for {} for {}s", host, policy.breaker_cooldown_secs);
+
+        // Let a trial request through once the cooldown has passed
+        let host = host.to_string();
+        ic_cdk_timers::set_timer(Duration::from_secs(policy.breaker_cooldown_secs), move || {
+            CIRCUITS.with(|circuits| {
+                if let Some(circuit) = circuits.borrow_mut().get_mut(&host) {
+                    // A failed trial may have re-opened the circuit with a later deadline
+                    if matches!(circuit.state, CircuitState::Open { until } if time() >= until) {
+                        circuit.state = CircuitState::HalfOpen;
+                    }
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,256 @@
+use crate::github::parse_github_date;
+use crate::models::UserProfile;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+const NANOS_PER_YEAR: u64 = (365 * 24 + 6) * 3600 * 1_000_000_000; // 365.25 days
+
+// Account age bands in whole years: (upper bound, key, label); the last band is open-ended
+const ACCOUNT_AGE_BANDS: &[(u64, &str, &str)] = &[
+    (1, "0_1", "Under 1 year"),
+    (3, "1_3", "1-3 years"),
+    (5, "3_5", "3-5 years"),
+    (10, "5_10", "5-10 years"),
+    (u64::MAX, "10_plus", "10+ years"),
+];
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct PercentileRank {
+    pub scope: String, // "overall", "language:rust", "category:contribution"
+    pub label: String,
+    pub score: u64,
+    pub percentile: f64, // share of the group scoring lower, ties counted as half, 0 to 100
+    pub population: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum CohortKind {
+    AccountAge(String),      // band key, e.g. "1_3"
+    PrimaryLanguage(String), // canonical language slug
+}
+
+// How a user's overall reputation compares with users like them
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct CohortComparison {
+    pub cohort: CohortKind,
+    pub label: String,
+    pub population: u64,
+    pub score: u64,
+    pub percentile: f64,
+    pub median_score: u64,
+    pub average_score: f64,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct UserPercentiles {
+    pub overall: PercentileRank,
+    pub languages: Vec<PercentileRank>,  // languages the user scores in, strongest first
+    pub categories: Vec<PercentileRank>,
+    pub cohorts: Vec<CohortComparison>,
+    pub snapshot_at: u64,                // when the distributions were taken, 0 before the first snapshot
+}
+
+// Scores of one group of ranked users, lowest first
+#[derive(Clone, Debug, Default)]
+struct ScoreDistribution {
+    counts: Vec<(u64, u64)>, // (score, users)
+    population: u64,
+    total: u64,
+}
+
+impl ScoreDistribution {
+    fn percentile(&self, score: u64) -> f64 {
+        if self.population == 0 {
+            return 0.0;
+        }
+        let below: u64 = self.counts.iter().take_while(|(s, _)| *s < score).map(|(_, n)| n).sum();
+        let equal = self.counts.iter().find(|(s, _)| *s == score).map_or(0, |(_, n)| *n);
+        let percentile = (below as f64 + equal as f64 / 2.0) / self.population as f64 * 100.0;
+        percentile.clamp(0.0, 100.0)
+    }
+
+    fn median(&self) -> u64 {
+        let middle = self.population.saturating_sub(1) / 2;
+        let mut seen = 0;
+        for (score, users) in &self.counts {
+            seen += users;
+            if seen > middle {
+                return *score;
+            }
+        }
+        0
+    }
+
+    fn average(&self) -> f64 {
+        if self.population == 0 {
+            return 0.0;
+        }
+        self.total as f64 / self.population as f64
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PercentileSnapshot {
+    pub computed_at: u64,
+    groups: HashMap<String, ScoreDistribution>,
+}
+
+impl PercentileSnapshot {
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+}
+
+thread_local! {
+    // Recomputed by the refresh timer, so it does not need to survive upgrades
+    static PERCENTILES: RefCell<PercentileSnapshot> = RefCell::new(PercentileSnapshot::default());
+}
+
+/// The groups a profile is counted in, with its score in each. Language groups only hold users
+/// scoring in that language; category and cohort groups hold every ranked user.
+fn group_scores(profile: &UserProfile, now: u64) -> Vec<(String, u64)> {
+    let mut groups = vec![("overall".to_string(), profile.reputation_score)];
+
+    if let Some(breakdown) = &profile.reputation_breakdown {
+        groups.extend(breakdown.languages.iter()
+            .filter(|l| l.score > 0)
+            .map(|l| (format!("language:{}", l.language), l.score)));
+        groups.extend([
+            ("category:language", breakdown.language),
+            ("category:contribution", breakdown.contribution),
+            ("category:achievement", breakdown.achievement),
+            ("category:special", breakdown.special),
+        ].map(|(group, score)| (group.to_string(), score)));
+    }
+
+    groups.extend(cohorts(profile, now).into_iter()
+        .map(|(cohort, _)| (cohort_group(&cohort), profile.reputation_score)));
+    groups
+}
+
+/// The cohorts a profile belongs to, with display labels
+fn cohorts(profile: &UserProfile, now: u64) -> Vec<(CohortKind, String)> {
+    let mut cohorts = Vec::new();
+
+    let created_at = profile.github_data.as_ref().and_then(|d| parse_github_date(&d.created_at));
+    if let Some(created_at) = created_at {
+        let years = now.saturating_sub(created_at) / NANOS_PER_YEAR;
+        if let Some((_, key, label)) = ACCOUNT_AGE_BANDS.iter().find(|(upper, _, _)| years < *upper) {
+            cohorts.push((CohortKind::AccountAge(key.to_string()), format!("GitHub accounts {}", label.to_lowercase())));
+        }
+    }
+
+    // Primary language is the one the user scores highest in
+    let primary = profile.reputation_breakdown.as_ref().and_then(|b| b.languages.first());
+    if let Some(language) = primary.filter(|l| l.score > 0) {
+        cohorts.push((
+            CohortKind::PrimaryLanguage(language.language.clone()),
+            format!("{} developers", language.name),
+        ));
+    }
+    cohorts
+}
+
+fn cohort_group(cohort: &CohortKind) -> String {
+    match cohort {
+        CohortKind::AccountAge(band) => format!("cohort:age:{}", band),
+        CohortKind::PrimaryLanguage(language) => format!("cohort:language:{}", language),
+    }
+}
+
+// Users per score in every group, accumulated over batches of profiles
+#[derive(Default)]
+pub struct PercentileCounts {
+    counts: HashMap<String, HashMap<u64, u64>>,
+}
+
+impl PercentileCounts {
+    pub fn add_profiles(&mut self, profiles: impl Iterator<Item = UserProfile>, now: u64) {
+        for profile in profiles.filter(|p| p.github_connected) {
+            for (group, score) in group_scores(&profile, now) {
+                *self.counts.entry(group).or_default().entry(score).or_insert(0) += 1;
+            }
+        }
+    }
+}
+
+/// Score distributions of every group across the counted GitHub-connected profiles
+pub fn compute_snapshot(counts: PercentileCounts, now: u64) -> PercentileSnapshot {
+    let groups = counts.counts.into_iter()
+        .map(|(group, scores)| {
+            let mut counts: Vec<(u64, u64)> = scores.into_iter().collect();
+            counts.sort_unstable();
+            let distribution = ScoreDistribution {
+                population: counts.iter().map(|(_, n)| n).sum(),
+                total: counts.iter().map(|(score, n)| score * n).sum(),
+                counts,
+            };
+            (group, distribution)
+        })
+        .collect();
+
+    PercentileSnapshot { computed_at: now, groups }
+}
+
+pub fn set_snapshot(snapshot: PercentileSnapshot) {
+    PERCENTILES.with(|percentiles| *percentiles.borrow_mut() = snapshot);
+}
+
+/// Where a profile's current scores fall in the latest snapshot
+pub fn user_percentiles(profile: &UserProfile, now: u64) -> Result<UserPercentiles, String> {
+    if !profile.github_connected {
+        return Err("Percentiles are only available for GitHub-connected profiles".to_string());
+    }
+
+    let scores: HashMap<String, u64> = group_scores(profile, now).into_iter().collect();
+    PERCENTILES.with(|percentiles| {
+        let snapshot = percentiles.borrow();
+        let empty = ScoreDistribution::default();
+        let rank = |group: &str, label: String| {
+            let score = scores.get(group).copied().unwrap_or(0);
+            let distribution = snapshot.groups.get(group);
+            PercentileRank {
+                scope: group.to_string(),
+                label,
+                score,
+                percentile: distribution.map_or(0.0, |d| d.percentile(score)),
+                population: distribution.map_or(0, |d| d.population),
+            }
+        };
+
+        let languages = profile.reputation_breakdown.iter()
+            .flat_map(|b| b.languages.iter())
+            .filter(|l| l.score > 0)
+            .map(|l| rank(&format!("language:{}", l.language), l.name.clone()))
+            .collect();
+
+        let categories = ["Language", "Contribution", "Achievement", "Special"].iter()
+            .map(|name| rank(&format!("category:{}", name.to_lowercase()), name.to_string()))
+            .collect();
+
+        let cohorts = cohorts(profile, now).into_iter()
+            .map(|(cohort, label)| {
+                let distribution = snapshot.groups.get(&cohort_group(&cohort)).unwrap_or(&empty);
+                CohortComparison {
+                    cohort,
+                    label,
+                    population: distribution.population,
+                    score: profile.reputation_score,
+                    percentile: distribution.percentile(profile.reputation_score),
+                    median_score: distribution.median(),
+                    average_score: distribution.average(),
+                }
+            })
+            .collect();
+
+        Ok(UserPercentiles {
+            overall: rank("overall", "Overall".to_string()),
+            languages,
+            categories,
+            cohorts,
+            snapshot_at: snapshot.computed_at,
+        })
+    })
+}
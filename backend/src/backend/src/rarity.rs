@@ -0,0 +1,112 @@
+use crate::badge_ids;
+use crate::models::{BadgeTier, UserProfile};
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// How rare a badge is at a given tier, measured across all GitHub-connected profiles
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BadgeRarity {
+    pub badge_id: String,
+    pub tier: BadgeTier,
+    pub holders: u64,             // profiles holding exactly this tier
+    pub holders_at_or_above: u64, // profiles holding this tier or a higher one
+    pub holder_fraction: f64,     // holders_at_or_above / population
+    pub rarity_score: u32,        // 0 (everyone has it) to 1000 (nobody has it)
+}
+
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct RarityTable {
+    pub population: u64,
+    pub computed_at: u64,
+    pub entries: Vec<BadgeRarity>,
+}
+
+const MAX_RARITY_SCORE: u32 = 1000;
+
+thread_local! {
+    // Recomputed by the refresh timer, so it does not need to survive upgrades
+    static RARITY: RefCell<RarityTable> = RefCell::new(RarityTable::default());
+}
+
+// Holders per badge id and tier, accumulated over batches of profiles
+#[derive(Default)]
+pub struct RarityCounts {
+    population: u64,
+    holders: HashMap<String, HashMap<BadgeTier, u64>>,
+}
+
+impl RarityCounts {
+    pub fn add_profiles(&mut self, profiles: impl Iterator<Item = UserProfile>) {
+        for profile in profiles.filter(|p| p.github_connected) {
+            self.population += 1;
+            for badge in &profile.badges {
+                *self.holders.entry(badge_ids::canonical_badge_id(&badge.id)).or_default()
+                    .entry(badge.tier.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    pub fn population(&self) -> u64 {
+        self.population
+    }
+}
+
+/// Derive rarity scores from the counted holders
+pub fn compute_rarity(counts: RarityCounts, now: u64) -> RarityTable {
+    let RarityCounts { population, holders } = counts;
+
+    let mut entries = Vec::new();
+    for (badge_id, tiers) in holders {
+        let mut tiers: Vec<(BadgeTier, u64)> = tiers.into_iter().collect();
+        tiers.sort_by(|a, b| b.0.cmp(&a.0));
+
+        // Walk from the highest tier down so each entry counts holders at or above it
+        let mut at_or_above = 0;
+        for (tier, count) in tiers {
+            at_or_above += count;
+            let holder_fraction = at_or_above as f64 / population as f64;
+            entries.push(BadgeRarity {
+                badge_id: badge_id.clone(),
+                tier,
+                holders: count,
+                holders_at_or_above: at_or_above,
+                holder_fraction,
+                rarity_score: score_for_fraction(holder_fraction),
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.badge_id.cmp(&b.badge_id).then(a.tier.cmp(&b.tier)));
+
+    RarityTable { population, computed_at: now, entries }
+}
+
+fn score_for_fraction(holder_fraction: f64) -> u32 {
+    ((1.0 - holder_fraction.clamp(0.0, 1.0)) * MAX_RARITY_SCORE as f64).round() as u32
+}
+
+pub fn set_rarity_table(table: RarityTable) {
+    RARITY.with(|rarity| *rarity.borrow_mut() = table);
+}
+
+pub fn get_rarity_table() -> RarityTable {
+    RARITY.with(|rarity| rarity.borrow().clone())
+}
+
+/// Rarity score for a badge tier; tiers nobody holds yet are maximally rare.
+/// None until the first refresh has run.
+pub fn rarity_score(badge_id: &str, tier: &BadgeTier) -> Option<u32> {
+    RARITY.with(|rarity| {
+        let rarity = rarity.borrow();
+        if rarity.population == 0 {
+            return None;
+        }
+
+        let at_or_above = rarity.entries.iter()
+            .filter(|e| e.badge_id == badge_id && e.tier >= *tier)
+            .map(|e| e.holders)
+            .sum::<u64>();
+        Some(score_for_fraction(at_or_above as f64 / rarity.population as f64))
+    })
+}
@@ -0,0 +1,143 @@
+use crate::badge_ids;
+use crate::models::{Badge, BadgeCategory, LanguageScore, ReputationBreakdown, UserProfile};
+use crate::{Memory, MEMORY_MANAGER, REPUTATION_CONFIG_MEMORY_ID};
+use candid::CandidType;
+use ic_stable_structures::{StableCell, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+const NANOS_PER_DAY: u64 = 24 * 3600 * 1_000_000_000;
+const MAX_HALF_LIFE_DAYS: u32 = 10 * 365;
+
+// How badge points age in the current reputation; lifetime reputation never decays
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct ReputationConfig {
+    pub decay_enabled: bool,
+    pub half_life_days: u32, // a badge counts half as much this long after it was last earned or upgraded
+}
+
+impl Default for ReputationConfig {
+    fn default() -> Self {
+        Self {
+            decay_enabled: false,
+            half_life_days: 365,
+        }
+    }
+}
+
+impl Storable for ReputationConfig {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+thread_local! {
+    static REPUTATION_CONFIG: RefCell<StableCell<ReputationConfig, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REPUTATION_CONFIG_MEMORY_ID)),
+            ReputationConfig::default(),
+        ).expect("Failed to initialize reputation config")
+    );
+}
+
+pub fn get_reputation_config() -> ReputationConfig {
+    REPUTATION_CONFIG.with(|cell| cell.borrow().get().clone())
+}
+
+pub fn set_reputation_config(config: ReputationConfig) -> Result<(), String> {
+    if config.half_life_days == 0 || config.half_life_days > MAX_HALF_LIFE_DAYS {
+        return Err(format!("Half-life must be between 1 and {} days", MAX_HALF_LIFE_DAYS));
+    }
+
+    REPUTATION_CONFIG.with(|cell| {
+        cell.borrow_mut().set(config)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to store reputation config: {:?}", e))
+    })
+}
+
+/// Tier points a badge currently contributes: they halve every half-life since the badge was
+/// last earned, upgraded or re-confirmed by an analysis, and never decay when decay is disabled
+fn badge_points(badge: &Badge, config: &ReputationConfig, now: u64) -> f64 {
+    let points = badge.tier.get_points() as f64;
+    if !config.decay_enabled {
+        return points;
+    }
+
+    let half_life_ns = config.half_life_days as f64 * NANOS_PER_DAY as f64;
+    let last_active = badge.upgraded_at.into_iter()
+        .chain(badge.last_confirmed_at)
+        .fold(badge.earned_at, u64::max);
+    let age_ns = now.saturating_sub(last_active) as f64;
+    points * 0.5f64.powf(age_ns / half_life_ns)
+}
+
+/// Current reputation split by badge category, with language badges (including AI-verified
+/// expertise in a language) also scored per language
+pub fn reputation_breakdown(badges: &[Badge], config: &ReputationConfig, now: u64) -> ReputationBreakdown {
+    let mut categories = [0f64; 4];
+    let mut languages: Vec<(String, f64)> = Vec::new();
+
+    for badge in badges {
+        let points = badge_points(badge, config, now);
+        let index = match &badge.category {
+            BadgeCategory::Language(language) => {
+                let slug = badge_ids::canonical_slug(language);
+                match languages.iter_mut().find(|(l, _)| *l == slug) {
+                    Some((_, score)) => *score += points,
+                    None => languages.push((slug, points)),
+                }
+                0
+            }
+            BadgeCategory::Contribution(_) => 1,
+            BadgeCategory::Achievement(_) => 2,
+            BadgeCategory::Special(_) => 3,
+        };
+        categories[index] += points;
+    }
+
+    let mut languages: Vec<LanguageScore> = languages.into_iter()
+        .map(|(slug, score)| LanguageScore {
+            name: badge_ids::language_display_name(&slug),
+            language: slug,
+            score: score.round() as u64,
+        })
+        .collect();
+    languages.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.language.cmp(&b.language)));
+
+    ReputationBreakdown {
+        language: categories[0].round() as u64,
+        contribution: categories[1].round() as u64,
+        achievement: categories[2].round() as u64,
+        special: categories[3].round() as u64,
+        languages,
+        computed_at: now,
+    }
+}
+
+/// Recompute lifetime and current reputation and the breakdown; `reputation_score` follows the
+/// current value, so rankings favour recent activity once decay is enabled. Returns whether any
+/// score changed.
+pub fn apply_reputation(profile: &mut UserProfile, config: &ReputationConfig, now: u64) -> bool {
+    let lifetime = crate::utils::calculate_reputation_score(&profile.badges);
+    let breakdown = reputation_breakdown(&profile.badges, config, now);
+    let current = breakdown.total();
+
+    let changed = profile.lifetime_reputation != Some(lifetime)
+        || profile.current_reputation != Some(current)
+        || profile.reputation_score != current
+        || profile.reputation_breakdown.as_ref().is_none_or(|b| !b.same_scores(&breakdown));
+
+    profile.lifetime_reputation = Some(lifetime);
+    profile.current_reputation = Some(current);
+    profile.reputation_score = current;
+    profile.reputation_breakdown = Some(breakdown);
+    changed
+}
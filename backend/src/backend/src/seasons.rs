@@ -0,0 +1,228 @@
+use crate::badge_ids;
+use crate::badge_rules::{self, TierThreshold};
+use crate::github::parse_github_date;
+use crate::models::{Badge, BadgeAttribute, BadgeCategory, BadgeEvidence, BadgeMetadata, BadgeTier, GitHubAnalysis, Repository, TierProgress};
+use crate::{Memory, MEMORY_MANAGER, SEASONS_MEMORY_ID};
+use candid::CandidType;
+use ic_stable_structures::{StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+// Activity measured only over repositories with timestamps inside the season window
+#[derive(Clone, Debug, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum SeasonMetric {
+    ReposCreated,     // repositories created during the season
+    ReposPushed,      // repositories pushed to during the season
+    StarsOnNewRepos,  // stars on repositories created during the season
+    LanguagesPushed,  // distinct languages of repositories pushed to during the season
+}
+
+// A time-boxed event badge that can only be earned between starts_at and ends_at
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct Season {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub starts_at: u64,
+    pub ends_at: u64,
+    pub metric: SeasonMetric,
+    pub tiers: Vec<TierThreshold>,
+    pub image_url: String,
+    pub animation_url: Option<String>,
+    pub created_at: u64,
+    pub closed_at: Option<u64>, // set when an admin closes the season early
+}
+
+impl SeasonMetric {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            SeasonMetric::ReposCreated => "repositories created",
+            SeasonMetric::ReposPushed => "repositories pushed to",
+            SeasonMetric::StarsOnNewRepos => "stars on new repositories",
+            SeasonMetric::LanguagesPushed => "languages used",
+        }
+    }
+}
+
+impl Season {
+    pub fn is_active(&self, now: u64) -> bool {
+        self.closed_at.is_none() && now >= self.starts_at && now < self.ends_at
+    }
+
+    pub fn badge_id(&self) -> String {
+        format!("season_{}", self.id)
+    }
+}
+
+impl Storable for Season {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+type SeasonStore = StableBTreeMap<String, Season, Memory>;
+
+const MAX_SEASON_LENGTH_NS: u64 = 366 * 24 * 3600 * 1_000_000_000;
+
+thread_local! {
+    static SEASONS: RefCell<SeasonStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SEASONS_MEMORY_ID)),
+        )
+    );
+}
+
+pub fn create_season(mut season: Season, now: u64) -> Result<Season, String> {
+    badge_rules::validate_badge_identity(&season.id, &season.name, &season.image_url)?;
+    badge_rules::validate_tiers(&season.tiers)?;
+    if season.ends_at <= season.starts_at {
+        return Err("Season must end after it starts".to_string());
+    }
+    if season.ends_at - season.starts_at > MAX_SEASON_LENGTH_NS {
+        return Err("Seasons can last at most one year".to_string());
+    }
+    if season.ends_at <= now {
+        return Err("Season has already ended".to_string());
+    }
+    if SEASONS.with(|seasons| seasons.borrow().contains_key(&season.id)) {
+        return Err(format!("Season '{}' already exists", season.id));
+    }
+
+    // Percentile thresholds need a population; seasons use their fixed minimums only
+    for tier in season.tiers.iter_mut() {
+        tier.min_percentile = None;
+    }
+    season.created_at = now;
+    season.closed_at = None;
+
+    SEASONS.with(|seasons| {
+        seasons.borrow_mut().insert(season.id.clone(), season.clone());
+    });
+
+    Ok(season)
+}
+
+pub fn close_season(id: &str, now: u64) -> Result<Season, String> {
+    SEASONS.with(|seasons| {
+        let mut seasons = seasons.borrow_mut();
+        let mut season = seasons.get(&id.to_string())
+            .ok_or_else(|| format!("Season '{}' not found", id))?;
+        if season.closed_at.is_some() {
+            return Err(format!("Season '{}' is already closed", id));
+        }
+
+        season.closed_at = Some(now);
+        season.ends_at = season.ends_at.min(now);
+        seasons.insert(season.id.clone(), season.clone());
+        Ok(season)
+    })
+}
+
+pub fn get_seasons() -> Vec<Season> {
+    SEASONS.with(|seasons| seasons.borrow().iter().map(|(_, s)| s).collect())
+}
+
+/// Evaluate every active season against the activity inside its window
+pub fn evaluate_season_badges(analysis: &GitHubAnalysis, now: u64) -> Vec<Badge> {
+    get_seasons().iter()
+        .filter(|season| season.is_active(now))
+        .filter_map(|season| evaluate_season(season, analysis, now))
+        .collect()
+}
+
+/// Progress towards the next tier of every active season
+pub fn evaluate_season_progress(analysis: &GitHubAnalysis, now: u64) -> Vec<TierProgress> {
+    get_seasons().iter()
+        .filter(|season| season.is_active(now))
+        .map(|season| {
+            let thresholds: Vec<(BadgeTier, u32)> = season.tiers.iter()
+                .map(|t| (t.tier.clone(), t.min_value))
+                .collect();
+            badge_rules::tier_progress(&season.badge_id(), &season.name, season_metric_value(season, &analysis.repositories), &thresholds)
+        })
+        .collect()
+}
+
+fn evaluate_season(season: &Season, analysis: &GitHubAnalysis, now: u64) -> Option<Badge> {
+    let value = season_metric_value(season, &analysis.repositories);
+    let threshold = season.tiers.iter().rev().find(|t| value >= t.min_value)?;
+
+    Some(Badge {
+        id: season.badge_id(),
+        name: season.name.clone(),
+        description: season.description.clone(),
+        category: BadgeCategory::Special("Season".to_string()),
+        tier: threshold.tier.clone(),
+        earned_at: now,
+        criteria_met: vec![format!("{} - {} {} during {}", threshold.label, value, season.metric.describe(), season.name)],
+        score_achieved: value,
+        upgraded_at: None,
+        tier_history: None,
+        nft_token_id: None,
+        at_risk_since: None,
+        season: Some(season.id.clone()),
+        rule_version: None,
+        last_confirmed_at: None,
+        evidence: Some(BadgeEvidence::from_inputs(&[
+            ("season", season.id.clone()),
+            ("metric", format!("{:?}", season.metric)),
+            ("value", value.to_string()),
+            ("window", format!("{}..{}", season.starts_at, season.ends_at)),
+        ])),
+        metadata: BadgeMetadata {
+            image_url: season.image_url.clone(),
+            animation_url: season.animation_url.clone(),
+            attributes: vec![
+                BadgeAttribute {
+                    trait_type: "Season".to_string(),
+                    value: season.name.clone(),
+                    display_type: None,
+                },
+                BadgeAttribute {
+                    trait_type: "Season Ends".to_string(),
+                    value: season.ends_at.to_string(),
+                    display_type: Some("date".to_string()),
+                },
+                BadgeAttribute {
+                    trait_type: "Tier".to_string(),
+                    value: threshold.tier.get_display_name().to_string(),
+                    display_type: None,
+                },
+            ],
+            rarity_score: threshold.tier.get_points(),
+        },
+    })
+}
+
+fn season_metric_value(season: &Season, repositories: &[Repository]) -> u32 {
+    let in_window = |date: &str| {
+        parse_github_date(date).is_some_and(|t| t >= season.starts_at && t < season.ends_at)
+    };
+
+    match season.metric {
+        SeasonMetric::ReposCreated => repositories.iter()
+            .filter(|r| !r.is_fork && in_window(&r.created_at))
+            .count() as u32,
+        SeasonMetric::ReposPushed => repositories.iter()
+            .filter(|r| in_window(&r.pushed_at))
+            .count() as u32,
+        SeasonMetric::StarsOnNewRepos => repositories.iter()
+            .filter(|r| !r.is_fork && in_window(&r.created_at))
+            .map(|r| r.stars)
+            .sum(),
+        SeasonMetric::LanguagesPushed => repositories.iter()
+            .filter(|r| in_window(&r.pushed_at))
+            .filter_map(|r| r.language.as_deref())
+            .map(badge_ids::canonical_slug)
+            .collect::<HashSet<_>>()
+            .len() as u32,
+    }
+}
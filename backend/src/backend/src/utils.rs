@@ -1,23 +1,20 @@
-use crate::models::{Badge, GitHubAnalysis, BadgeCategory, BadgeTier, BadgeMetadata, BadgeAttribute};
+use crate::badge_ids;
+use crate::badge_rules::{self, BadgeRevocationPolicy, BadgeRuleSet, RevocationMode};
+use crate::models::{AnalysisDeltas, Badge, BadgeEvidence, GitHubAnalysis, BadgeCategory, BadgeTier, BadgeMetadata, BadgeAttribute, Repository, RevokedBadge, TierChange, TierProgress};
 use ic_cdk::api::time;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 
 /// Generate badges based on GitHub analysis with new Bronze/Silver/Gold tier system
-pub fn generate_badges_from_analysis(analysis: &GitHubAnalysis) -> Vec<Badge> {
+pub fn generate_badges_from_analysis(analysis: &GitHubAnalysis, rule_set: &BadgeRuleSet) -> Vec<Badge> {
     let mut badges = Vec::new();
     let current_time = time();
 
     // Language badges based on usage and proficiency
     badges.extend(generate_language_badges(analysis, current_time));
 
-    // Contribution badges based on activity and consistency
-    badges.extend(generate_contribution_badges(analysis, current_time));
-
-    // Achievement badges based on milestones and special accomplishments
-    badges.extend(generate_achievement_badges(analysis, current_time));
-
-    // Special badges for unique achievements
-    badges.extend(generate_special_badges(analysis, current_time));
+    // Contribution, achievement and special badges from the admin-editable rule set
+    badges.extend(badge_rules::evaluate_badge_rules(rule_set, analysis, current_time));
 
     ic_cdk::println!("Generated {} badges for user: {}", badges.len(), analysis.username);
     badges
@@ -32,12 +29,13 @@ fn generate_language_badges(analysis: &GitHubAnalysis, current_time: u64) -> Vec
     let languages = extract_languages_from_repos(&analysis.repositories);
 
     for (language, usage_score) in languages {
-        let (tier, criteria_met, score) = determine_language_tier(usage_score, &analysis.repositories, &language);
+        let (tier, criteria_met, score, evidence) = determine_language_tier(usage_score, &analysis.repositories, &language);
 
         if let Some(badge_tier) = tier {
             let badge_tier_clone = badge_tier.clone();
+            let slug = badge_ids::canonical_slug(&language);
             badges.push(Badge {
-                id: format!("lang_{}", language.to_lowercase()),
+                id: badge_ids::language_badge_id(&language),
                 name: format!("{} Expert", language),
                 description: format!("Demonstrated expertise in {} programming", language),
                 category: BadgeCategory::Language(language.clone()),
@@ -45,9 +43,17 @@ fn generate_language_badges(analysis: &GitHubAnalysis, current_time: u64) -> Vec
                 earned_at: current_time,
                 criteria_met,
                 score_achieved: score,
+                upgraded_at: None,
+                tier_history: None,
+                nft_token_id: None,
+                at_risk_since: None,
+                season: None,
+                rule_version: None,
+                last_confirmed_at: None,
+                evidence: Some(BadgeEvidence::from_inputs(&evidence)),
                 metadata: BadgeMetadata {
-                    image_url: format!("/badges/languages/{}.svg", language.to_lowercase()),
-                    animation_url: Some(format!("/badges/languages/{}_animated.gif", language.to_lowercase())),
+                    image_url: format!("/badges/languages/{}.svg", slug),
+                    animation_url: Some(format!("/badges/languages/{}_animated.gif", slug)),
                     attributes: vec![
                         BadgeAttribute {
                             trait_type: "Language".to_string(),
@@ -74,416 +80,703 @@ fn generate_language_badges(analysis: &GitHubAnalysis, current_time: u64) -> Vec
     badges
 }
 
-/// Generate contribution-based badges
-fn generate_contribution_badges(analysis: &GitHubAnalysis, current_time: u64) -> Vec<Badge> {
-    let mut badges = Vec::new();
+// Helper functions for tier determination
 
-    // Open Source Contributor badge
-    let open_source_score = calculate_open_source_score(analysis);
-    if let Some((tier, criteria)) = determine_contribution_tier(open_source_score, "open_source") {
-        badges.push(create_contribution_badge(
-            "open_source_contributor",
-            "Open Source Contributor",
-            "Active contributor to open source projects",
-            BadgeCategory::Contribution("OpenSource".to_string()),
-            tier,
-            criteria,
-            open_source_score,
-            current_time,
-        ));
-    }
-
-    // Community Builder badge
-    let community_score = analysis.community_engagement_score as u32;
-    if let Some((tier, criteria)) = determine_contribution_tier(community_score, "community") {
-        badges.push(create_contribution_badge(
-            "community_builder",
-            "Community Builder",
-            "Building and engaging with the developer community",
-            BadgeCategory::Contribution("Community".to_string()),
-            tier,
-            criteria,
-            community_score,
-            current_time,
-        ));
-    }
-
-    // Consistent Contributor badge
-    let consistency_score = analysis.commit_frequency_score as u32;
-    if let Some((tier, criteria)) = determine_contribution_tier(consistency_score, "consistency") {
-        badges.push(create_contribution_badge(
-            "consistent_contributor",
-            "Consistent Contributor",
-            "Maintaining consistent contribution patterns",
-            BadgeCategory::Contribution("Consistency".to_string()),
-            tier,
-            criteria,
-            consistency_score,
-            current_time,
-        ));
-    }
+// Language tiers are driven by one score that never decreases when volume, repo count or
+// recent activity grows, so more usage can never yield a lower tier
+pub(crate) const MIN_LANGUAGE_VOLUME: u32 = 1000;
+const MAX_SCORED_LANGUAGE_REPOS: u32 = 50;
+const MAX_SCORED_RECENT_REPOS: u32 = 10;
+const RECENT_ACTIVITY_NS: u64 = 2 * 365 * 24 * 60 * 60 * 1_000_000_000; // pushed within ~2 years
+
+pub(crate) const LANGUAGE_TIERS: [(u32, BadgeTier, &str); 9] = [
+    (12, BadgeTier::Bronze1, "Hands-on"),
+    (34, BadgeTier::Bronze2, "Practiced"),
+    (46, BadgeTier::Bronze3, "Extensive"),
+    (70, BadgeTier::Silver1, "Professional-level"),
+    (86, BadgeTier::Silver2, "Advanced"),
+    (110, BadgeTier::Silver3, "Expert-level"),
+    (130, BadgeTier::Gold1, "Elite"),
+    (160, BadgeTier::Gold2, "Legendary"),
+    (210, BadgeTier::Gold3, "Master"),
+];
+
+/// Combined language score: log-scaled volume (10 points per doubling from 500), two points per
+/// repository (up to 50) and a recency bonus of two points per recently pushed repository (up to 10)
+pub fn language_score(volume: u32, repo_count: u32, recent_repo_count: u32) -> u32 {
+    let volume_points = (volume / 500 + 1).ilog2() * 10;
+    let repo_points = repo_count.min(MAX_SCORED_LANGUAGE_REPOS) * 2;
+    let recency_points = recent_repo_count.min(repo_count).min(MAX_SCORED_RECENT_REPOS) * 2;
+
+    volume_points + repo_points + recency_points
+}
 
-    badges
+/// Highest tier whose threshold the score meets; every eligible input scores at least Bronze I
+pub fn language_tier(score: u32) -> Option<(BadgeTier, &'static str)> {
+    LANGUAGE_TIERS.iter()
+        .rev()
+        .find(|(threshold, _, _)| score >= *threshold)
+        .map(|(_, tier, label)| (tier.clone(), *label))
 }
 
-/// Generate achievement badges
-fn generate_achievement_badges(analysis: &GitHubAnalysis, current_time: u64) -> Vec<Badge> {
-    let mut badges = Vec::new();
+type LanguageEvidence = Vec<(&'static str, String)>;
 
-    // Repository Creator badge
-    if let Some((tier, criteria, score)) = determine_repository_achievement(analysis.total_repos) {
-        badges.push(create_achievement_badge(
-            "repository_creator",
-            "Repository Creator",
-            "Creating and maintaining multiple repositories",
-            tier,
-            criteria,
-            score,
-            current_time,
-        ));
-    }
-
-    // Commit Master badge
-    if let Some((tier, criteria, score)) = determine_commit_achievement(analysis.total_commits) {
-        badges.push(create_achievement_badge(
-            "commit_master",
-            "Commit Master",
-            "Accumulating significant commit history",
-            tier,
-            criteria,
-            score,
-            current_time,
-        ));
-    }
-
-    // Star Collector badge
-    if let Some((tier, criteria, score)) = determine_star_achievement(analysis.total_stars_received) {
-        badges.push(create_achievement_badge(
-            "star_collector",
-            "Star Collector",
-            "Creating popular and useful projects",
-            tier,
-            criteria,
-            score,
-            current_time,
-        ));
+fn determine_language_tier(usage_score: u32, repositories: &[crate::models::Repository], language: &str) -> (Option<BadgeTier>, Vec<String>, u32, LanguageEvidence) {
+    if usage_score < MIN_LANGUAGE_VOLUME {
+        return (None, Vec::new(), 0, Vec::new());
     }
 
-    badges
+    let now = time();
+    let language_repos: Vec<_> = repositories.iter()
+        .filter(|r| r.language.as_deref().is_some_and(|l| badge_ids::same_language(l, language)))
+        .collect();
+    let repos_with_language = (language_repos.len() as u32).max(1);
+    let recent_repos = language_repos.iter()
+        .filter(|r| {
+            crate::github::parse_github_date(&r.pushed_at)
+                .is_some_and(|pushed| now.saturating_sub(pushed) <= RECENT_ACTIVITY_NS)
+        })
+        .count() as u32;
+
+    let score = language_score(usage_score, repos_with_language, recent_repos);
+    let evidence = vec![
+        ("language", language.to_string()),
+        ("volume", usage_score.to_string()),
+        ("repositories", repos_with_language.to_string()),
+        ("recent_repositories", recent_repos.to_string()),
+        ("language_score", score.to_string()),
+    ];
+
+    match language_tier(score) {
+        Some((tier, label)) => {
+            let criteria = vec![format!(
+                "{} {} experience - score {} from {} usage across {} repositories ({} recently active)",
+                label, language, score, usage_score, repos_with_language, recent_repos
+            )];
+            (Some(tier), criteria, score, evidence)
+        }
+        None => (None, Vec::new(), score, evidence),
+    }
 }
 
-/// Generate special badges for unique achievements
-fn generate_special_badges(analysis: &GitHubAnalysis, current_time: u64) -> Vec<Badge> {
-    let mut badges = Vec::new();
+/// Progress towards the next tier of each language used. Below the minimum volume the badge is
+/// locked and progress is measured in volume, since reaching it alone earns Bronze I.
+pub fn evaluate_language_progress(analysis: &GitHubAnalysis) -> Vec<TierProgress> {
+    let mut languages: Vec<(String, u32)> = extract_languages_from_repos(&analysis.repositories).into_iter().collect();
+    languages.sort();
+
+    languages.into_iter()
+        .map(|(language, usage_score)| {
+            let badge_id = badge_ids::language_badge_id(&language);
+            let name = format!("{} Expert", language);
+            if usage_score < MIN_LANGUAGE_VOLUME {
+                return badge_rules::tier_progress(&badge_id, &name, usage_score, &[(BadgeTier::Bronze1, MIN_LANGUAGE_VOLUME)]);
+            }
 
-    // Early Adopter badge (account older than 5 years)
-    if analysis.account_age_days > 1825 {
-        badges.push(Badge {
-            id: "early_adopter".to_string(),
-            name: "Early Adopter".to_string(),
-            description: "Been on GitHub for over 5 years".to_string(),
-            category: BadgeCategory::Special("EarlyAdopter".to_string()),
-            tier: BadgeTier::Gold2,
-            earned_at: current_time,
-            criteria_met: vec!["Account age > 5 years".to_string()],
-            score_achieved: analysis.account_age_days / 365,
-            metadata: create_special_badge_metadata("early_adopter", analysis.account_age_days / 365),
-        });
-    }
-
-    // Extract languages for polyglot badge
-    let languages = extract_languages_from_repos(&analysis.repositories);
-    if languages.len() >= 5 {
-        let tier = match languages.len() {
-            5..=7 => BadgeTier::Bronze3,
-            8..=12 => BadgeTier::Silver2,
-            _ => BadgeTier::Gold1,
-        };
-
-        badges.push(Badge {
-            id: "polyglot".to_string(),
-            name: "Polyglot".to_string(),
-            description: format!("Codes in {} different languages", languages.len()),
-            category: BadgeCategory::Special("Polyglot".to_string()),
-            tier,
-            earned_at: current_time,
-            criteria_met: vec![format!("Uses {} programming languages", languages.len())],
-            score_achieved: languages.len() as u32,
-            metadata: create_special_badge_metadata("polyglot", languages.len() as u32),
-        });
-    }
-
-    // Innovation badge (high code quality score)
-    if analysis.code_quality_score >= 80.0 {
-        badges.push(Badge {
-            id: "innovator".to_string(),
-            name: "Innovator".to_string(),
-            description: "Consistently produces high-quality code".to_string(),
-            category: BadgeCategory::Special("Innovation".to_string()),
-            tier: BadgeTier::Gold3,
-            earned_at: current_time,
-            criteria_met: vec!["Code quality score > 80".to_string()],
-            score_achieved: analysis.code_quality_score as u32,
-            metadata: create_special_badge_metadata("innovator", analysis.code_quality_score as u32),
-        });
+            let (_, _, score, _) = determine_language_tier(usage_score, &analysis.repositories, &language);
+            let thresholds: Vec<(BadgeTier, u32)> = LANGUAGE_TIERS.iter()
+                .map(|(threshold, tier, _)| (tier.clone(), *threshold))
+                .collect();
+            badge_rules::tier_progress(&badge_id, &name, score, &thresholds)
+        })
+        .collect()
+}
+
+/// Apply hypothetical deltas to an analysis. Extra language volume is added to the largest
+/// repository in that language, or to a new private repository so other badges are unaffected.
+pub fn apply_analysis_deltas(analysis: &mut GitHubAnalysis, deltas: &AnalysisDeltas) {
+    let shift = |value: &mut u32, delta: Option<i64>| {
+        if let Some(delta) = delta {
+            *value = (*value as i64 + delta).clamp(0, u32::MAX as i64) as u32;
+        }
+    };
+    shift(&mut analysis.total_repos, deltas.total_repos);
+    shift(&mut analysis.total_commits, deltas.total_commits);
+    shift(&mut analysis.total_stars_received, deltas.total_stars_received);
+    shift(&mut analysis.total_forks_received, deltas.total_forks_received);
+    shift(&mut analysis.followers, deltas.followers);
+    shift(&mut analysis.account_age_days, deltas.account_age_days);
+
+    let shift_score = |value: &mut f32, delta: Option<f32>| {
+        if let Some(delta) = delta {
+            *value = (*value + delta).clamp(0.0, 100.0);
+        }
+    };
+    shift_score(&mut analysis.commit_frequency_score, deltas.commit_frequency_score);
+    shift_score(&mut analysis.code_quality_score, deltas.code_quality_score);
+    shift_score(&mut analysis.community_engagement_score, deltas.community_engagement_score);
+
+    for (language, volume) in &deltas.language_volume {
+        let largest = analysis.repositories.iter_mut()
+            .filter(|r| r.language.as_deref().is_some_and(|l| badge_ids::same_language(l, language)))
+            .max_by_key(|r| r.size);
+        match largest {
+            Some(repo) => repo.size = repo.size.saturating_add(*volume),
+            None => analysis.repositories.push(Repository {
+                name: format!("hypothetical-{}", badge_ids::canonical_slug(language)),
+                full_name: format!("{}/hypothetical-{}", analysis.username, badge_ids::canonical_slug(language)),
+                description: None,
+                language: Some(language.clone()),
+                stars: 0,
+                forks: 0,
+                size: *volume,
+                is_fork: false,
+                is_private: true,
+                created_at: String::new(),
+                updated_at: String::new(),
+                pushed_at: String::new(),
+                commits_count: None,
+            }),
+        }
+        let total = analysis.languages.entry(language.clone()).or_insert(0);
+        *total = total.saturating_add(*volume);
     }
+}
 
-    badges
+pub fn calculate_open_source_score(analysis: &GitHubAnalysis) -> u32 {
+    let public_repos = analysis.repositories.iter()
+        .filter(|r| !r.is_private && !r.is_fork)
+        .count() as u32;
+
+    let fork_factor = analysis.total_forks_received * 2;
+    let star_factor = analysis.total_stars_received;
+
+    public_repos * 5 + fork_factor + star_factor
 }
 
-// Helper functions for tier determination
+/// Volume per language, keyed by linguist display name so spelling variants are counted together
+pub fn extract_languages_from_repos(repositories: &[crate::models::Repository]) -> HashMap<String, u32> {
+    let mut languages = HashMap::new();
 
-fn determine_language_tier(usage_score: u32, repositories: &[crate::models::Repository], language: &str) -> (Option<BadgeTier>, Vec<String>, u32) {
-    let repos_with_language = repositories.iter()
-        .filter(|r| r.language.as_deref() == Some(language))
-        .count();
-
-    let mut criteria = Vec::new();
-    let score = usage_score / 100; // Normalize score
-
-    let tier = match (usage_score, repos_with_language) {
-        (1000..=5000, 1..=2) => {
-            criteria.push(format!("Written {}+ lines in {}", usage_score, language));
-            Some(BadgeTier::Bronze1)
-        },
-        (5001..=15000, 2..=5) => {
-            criteria.push(format!("Written {}+ lines across {} projects", usage_score, repos_with_language));
-            Some(BadgeTier::Bronze2)
-        },
-        (15001..=50000, 3..=8) => {
-            criteria.push(format!("Extensive {} experience across {} repositories", language, repos_with_language));
-            Some(BadgeTier::Bronze3)
-        },
-        (50001..=100000, 5..=15) => {
-            criteria.push(format!("Professional-level {} development", language));
-            Some(BadgeTier::Silver1)
-        },
-        (100001..=250000, 8..=20) => {
-            criteria.push(format!("Advanced {} expertise", language));
-            Some(BadgeTier::Silver2)
-        },
-        (250001..=500000, 15..=30) => {
-            criteria.push(format!("Expert-level {} mastery", language));
-            Some(BadgeTier::Silver3)
-        },
-        (500001..=1000000, 20..=50) => {
-            criteria.push(format!("Elite {} developer", language));
-            Some(BadgeTier::Gold1)
-        },
-        (1000001..=2000000, 30..) => {
-            criteria.push(format!("Legendary {} expertise", language));
-            Some(BadgeTier::Gold2)
-        },
-        (2000001.., 50..) => {
-            criteria.push(format!("Master {} architect", language));
-            Some(BadgeTier::Gold3)
-        },
-        _ => None,
-    };
+    for repo in repositories {
+        if let Some(lang) = &repo.language {
+            if !badge_ids::slugify(lang).is_empty() {
+                *languages.entry(badge_ids::language_display_name(lang)).or_insert(0) += repo.size;
+            }
+        }
+    }
 
-    (tier, criteria, score)
+    languages
 }
 
-fn determine_contribution_tier(score: u32, category: &str) -> Option<(BadgeTier, Vec<String>)> {
-    let (tier, description) = match score {
-        10..=25 => (BadgeTier::Bronze1, "Getting started"),
-        26..=50 => (BadgeTier::Bronze2, "Regular contributor"),
-        51..=75 => (BadgeTier::Bronze3, "Active participant"),
-        76..=100 => (BadgeTier::Silver1, "Dedicated contributor"),
-        101..=150 => (BadgeTier::Silver2, "Community leader"),
-        151..=200 => (BadgeTier::Silver3, "Influential member"),
-        201..=300 => (BadgeTier::Gold1, "Community champion"),
-        301..=500 => (BadgeTier::Gold2, "Elite contributor"),
-        501.. => (BadgeTier::Gold3, "Legendary figure"),
-        _ => return None,
-    };
+pub fn calculate_reputation_score(badges: &[Badge]) -> u64 {
+    badges.iter()
+        .map(|badge| badge.tier.get_points() as u64)
+        .sum()
+}
+// Badge ids affected by merging a fresh analysis into the badges a user already holds
+#[derive(Default)]
+pub struct BadgeProgress {
+    pub awarded: Vec<String>,
+    pub upgraded: Vec<String>,
+}
+
+/// Merge freshly generated badges into held ones: new ids are awarded, and a held badge
+/// reaching a higher tier is upgraded in place, keeping its original `earned_at`
+pub fn merge_badge_progress(held: &mut Vec<Badge>, generated: Vec<Badge>, now: u64) -> BadgeProgress {
+    let mut progress = BadgeProgress::default();
+
+    for mut badge in generated {
+        match held.iter_mut().find(|b| b.id == badge.id) {
+            Some(existing) if badge.tier > existing.tier => {
+                let mut history = take_tier_history(existing);
+                history.push(TierChange {
+                    tier: badge.tier.clone(),
+                    score_achieved: badge.score_achieved,
+                    changed_at: now,
+                    reason: None,
+                });
+
+                badge.earned_at = existing.earned_at;
+                badge.upgraded_at = Some(now);
+                badge.last_confirmed_at = Some(now);
+                badge.tier_history = Some(history);
+                badge.nft_token_id = existing.nft_token_id;
+
+                progress.upgraded.push(badge.id.clone());
+                *existing = badge;
+            }
+            // Still earned at the held tier: the badge is now confirmed under the current rules,
+            // by the evidence of this evaluation, as of the analysis it came from
+            Some(existing) => {
+                existing.last_confirmed_at = Some(badge.evidence.as_ref().map_or(now, |e| e.analyzed_at));
+                existing.rule_version = badge.rule_version;
+                existing.evidence = badge.evidence;
+            }
+            None => {
+                badge.tier_history = Some(vec![TierChange {
+                    tier: badge.tier.clone(),
+                    score_achieved: badge.score_achieved,
+                    changed_at: badge.earned_at,
+                    reason: None,
+                }]);
+
+                progress.awarded.push(badge.id.clone());
+                held.push(badge);
+            }
+        }
+    }
 
-    let criteria = vec![format!("{} in {} category with score {}", description, category, score)];
-    Some((tier, criteria))
+    progress
 }
 
-fn determine_repository_achievement(repo_count: u32) -> Option<(BadgeTier, Vec<String>, u32)> {
-    let (tier, description) = match repo_count {
-        5..=10 => (BadgeTier::Bronze1, "Created first repositories"),
-        11..=25 => (BadgeTier::Bronze2, "Regular repository creator"),
-        26..=50 => (BadgeTier::Bronze3, "Prolific project starter"),
-        51..=100 => (BadgeTier::Silver1, "Dedicated builder"),
-        101..=200 => (BadgeTier::Silver2, "Project factory"),
-        201..=300 => (BadgeTier::Silver3, "Repository master"),
-        301..=500 => (BadgeTier::Gold1, "Elite creator"),
-        501..=1000 => (BadgeTier::Gold2, "Legendary builder"),
-        1001.. => (BadgeTier::Gold3, "Repository titan"),
-        _ => return None,
-    };
+// NFTs affected by merging duplicate badges in `canonicalize_badge_ids`
+#[derive(Default)]
+pub struct MergedBadgeNfts {
+    pub relinked: Vec<String>, // kept badges that took over a dropped duplicate's NFT
+    pub orphaned: Vec<u64>,    // NFTs of dropped duplicates, to burn
+}
+
+/// Rewrite legacy badge ids to their canonical form and drop duplicates that now share an id,
+/// keeping the highest tier and the earliest `earned_at`. A kept badge without an NFT takes over the dropped one's; other
+/// NFTs of dropped duplicates are reported for burning. Returns whether anything changed.
+pub fn canonicalize_badge_ids(badges: &mut Vec<Badge>) -> (bool, MergedBadgeNfts) {
+    let mut changed = false;
+    let mut merged = MergedBadgeNfts::default();
+    let mut canonical: Vec<Badge> = Vec::with_capacity(badges.len());
+
+    for mut badge in badges.drain(..) {
+        let id = badge_ids::canonical_badge_id(&badge.id);
+        if id != badge.id {
+            badge.id = id;
+            changed = true;
+        }
+
+        match canonical.iter_mut().find(|b| b.id == badge.id) {
+            Some(existing) => {
+                changed = true;
+                let dropped = if badge.tier > existing.tier {
+                    std::mem::replace(existing, badge)
+                } else {
+                    badge
+                };
+                existing.earned_at = existing.earned_at.min(dropped.earned_at);
+                match (existing.nft_token_id, dropped.nft_token_id) {
+                    (None, Some(token_id)) => {
+                        existing.nft_token_id = Some(token_id);
+                        merged.relinked.push(existing.id.clone());
+                    }
+                    (Some(_), Some(token_id)) => merged.orphaned.push(token_id),
+                    _ => {}
+                }
+            }
+            None => canonical.push(badge),
+        }
+    }
 
-    let criteria = vec![format!("{} - {} repositories created", description, repo_count)];
-    Some((tier, criteria, repo_count))
+    *badges = canonical;
+    (changed, merged)
 }
 
-fn determine_commit_achievement(commit_count: u32) -> Option<(BadgeTier, Vec<String>, u32)> {
-    let (tier, description) = match commit_count {
-        100..=500 => (BadgeTier::Bronze1, "Getting into the rhythm"),
-        501..=1000 => (BadgeTier::Bronze2, "Regular committer"),
-        1001..=2500 => (BadgeTier::Bronze3, "Consistent contributor"),
-        2501..=5000 => (BadgeTier::Silver1, "Dedicated coder"),
-        5001..=10000 => (BadgeTier::Silver2, "Prolific committer"),
-        10001..=25000 => (BadgeTier::Silver3, "Commit machine"),
-        25001..=50000 => (BadgeTier::Gold1, "Elite contributor"),
-        50001..=100000 => (BadgeTier::Gold2, "Legendary committer"),
-        100001.. => (BadgeTier::Gold3, "Commit titan"),
-        _ => return None,
-    };
+/// Tier history of a badge, seeded with its original tier for badges earned before history was kept
+fn take_tier_history(badge: &mut Badge) -> Vec<TierChange> {
+    badge.tier_history.take().unwrap_or_else(|| vec![TierChange {
+        tier: badge.tier.clone(),
+        score_achieved: badge.score_achieved,
+        changed_at: badge.earned_at,
+        reason: None,
+    }])
+}
+
+// Outcome of reconciling held badges against a fresh analysis
+#[derive(Default)]
+pub struct BadgeReconciliation {
+    pub downgraded: Vec<String>,
+    pub revoked: Vec<RevokedBadge>,
+    pub unsupported: Vec<String>, // re-checked badges the fresh data no longer supports, whatever the policy
+}
 
-    let criteria = vec![format!("{} - {} commits made", description, commit_count)];
-    Some((tier, criteria, commit_count))
+/// Whether a held badge is re-checked against a fresh analysis: seasonal badges are kept for
+/// good, and AI badges only when the LLM evaluated the profile in this run
+pub fn reevaluated_by_analysis(badge: &Badge, ai_evaluated: bool) -> bool {
+    badge.season.is_none() && (ai_evaluated || !badge.id.starts_with("ai_"))
 }
 
-fn determine_star_achievement(star_count: u32) -> Option<(BadgeTier, Vec<String>, u32)> {
-    let (tier, description) = match star_count {
-        10..=50 => (BadgeTier::Bronze1, "Gaining recognition"),
-        51..=150 => (BadgeTier::Bronze2, "Community appreciated"),
-        151..=300 => (BadgeTier::Bronze3, "Popular creator"),
-        301..=750 => (BadgeTier::Silver1, "Widely recognized"),
-        751..=1500 => (BadgeTier::Silver2, "Highly regarded"),
-        1501..=3000 => (BadgeTier::Silver3, "Community favorite"),
-        3001..=7500 => (BadgeTier::Gold1, "Influential creator"),
-        7501..=15000 => (BadgeTier::Gold2, "Legendary developer"),
-        15001.. => (BadgeTier::Gold3, "Star magnet"),
-        _ => return None,
+/// Reconcile held badges against the freshly generated set according to the revocation policy.
+/// Badge ids rejected by `evaluated` were not re-checked in this run and are left untouched.
+pub fn reconcile_badges(
+    held: &mut Vec<Badge>,
+    generated: &[Badge],
+    policy: &BadgeRevocationPolicy,
+    evaluated: impl Fn(&Badge) -> bool,
+    now: u64,
+) -> BadgeReconciliation {
+    let mut reconciliation = BadgeReconciliation {
+        unsupported: held.iter()
+            .filter(|b| evaluated(b) && !generated.iter().any(|g| g.id == b.id))
+            .map(|b| b.id.clone())
+            .collect(),
+        ..Default::default()
     };
+    if policy.mode == RevocationMode::Never {
+        return reconciliation;
+    }
 
-    let criteria = vec![format!("{} - {} stars received", description, star_count)];
-    Some((tier, criteria, star_count))
-}
+    let grace_period_ns = policy.grace_period_secs.saturating_mul(1_000_000_000);
+    let mut kept = Vec::with_capacity(held.len());
 
-// Helper functions for badge creation
-
-fn create_contribution_badge(
-    id: &str,
-    name: &str,
-    description: &str,
-    category: BadgeCategory,
-    tier: BadgeTier,
-    criteria: Vec<String>,
-    score: u32,
-    earned_at: u64,
-) -> Badge {
-    let tier_clone = tier.clone();
-    Badge {
-        id: id.to_string(),
-        name: name.to_string(),
-        description: description.to_string(),
-        category,
-        tier,
-        earned_at,
-        criteria_met: criteria,
-        score_achieved: score,
-        metadata: BadgeMetadata {
-            image_url: format!("/badges/contributions/{}.svg", id),
-            animation_url: Some(format!("/badges/contributions/{}_animated.gif", id)),
-            attributes: vec![
-                BadgeAttribute {
-                    trait_type: "Category".to_string(),
-                    value: "Contribution".to_string(),
-                    display_type: None,
-                },
-                BadgeAttribute {
-                    trait_type: "Score".to_string(),
-                    value: score.to_string(),
-                    display_type: Some("number".to_string()),
-                },
-            ],
-            rarity_score: tier_clone.get_points(),
-        },
+    for mut badge in held.drain(..) {
+        if !evaluated(&badge) {
+            kept.push(badge);
+            continue;
+        }
+
+        match generated.iter().find(|b| b.id == badge.id) {
+            Some(fresh) => {
+                badge.at_risk_since = None;
+
+                if fresh.tier < badge.tier {
+                    let reason = format!(
+                        "Score dropped from {} to {}, below the {} threshold",
+                        badge.score_achieved, fresh.score_achieved, badge.tier.get_display_name()
+                    );
+                    let mut history = take_tier_history(&mut badge);
+                    history.push(TierChange {
+                        tier: fresh.tier.clone(),
+                        score_achieved: fresh.score_achieved,
+                        changed_at: now,
+                        reason: Some(reason),
+                    });
+
+                    badge.tier = fresh.tier.clone();
+                    badge.score_achieved = fresh.score_achieved;
+                    badge.criteria_met = fresh.criteria_met.clone();
+                    badge.metadata = fresh.metadata.clone();
+                    badge.evidence = fresh.evidence.clone();
+                    badge.tier_history = Some(history);
+
+                    reconciliation.downgraded.push(badge.id.clone());
+                }
+                kept.push(badge);
+            }
+            None if policy.mode == RevocationMode::RevokeAfterGrace => {
+                let at_risk_since = *badge.at_risk_since.get_or_insert(now);
+                if now.saturating_sub(at_risk_since) >= grace_period_ns {
+                    let reason = format!(
+                        "Criteria no longer met for {} days",
+                        (now - at_risk_since) / (24 * 3600 * 1_000_000_000)
+                    );
+                    reconciliation.revoked.push(RevokedBadge { badge, reason, revoked_at: now });
+                } else {
+                    kept.push(badge);
+                }
+            }
+            None => kept.push(badge),
+        }
     }
+
+    *held = kept;
+    reconciliation
 }
 
-fn create_achievement_badge(
-    id: &str,
-    name: &str,
-    description: &str,
-    tier: BadgeTier,
-    criteria: Vec<String>,
-    score: u32,
-    earned_at: u64,
-) -> Badge {
-    let tier_clone = tier.clone();
-    Badge {
-        id: id.to_string(),
-        name: name.to_string(),
-        description: description.to_string(),
-        category: BadgeCategory::Achievement(name.to_string()),
-        tier,
-        earned_at,
-        criteria_met: criteria,
-        score_achieved: score,
-        metadata: BadgeMetadata {
-            image_url: format!("/badges/achievements/{}.svg", id),
-            animation_url: Some(format!("/badges/achievements/{}_animated.gif", id)),
-            attributes: vec![
-                BadgeAttribute {
-                    trait_type: "Achievement Type".to_string(),
-                    value: name.to_string(),
-                    display_type: None,
-                },
-                BadgeAttribute {
-                    trait_type: "Score".to_string(),
-                    value: score.to_string(),
-                    display_type: Some("number".to_string()),
-                },
-            ],
-            rarity_score: tier_clone.get_points(),
-        },
-    }
+/// SHA-256 over the analysis as JSON with sorted object keys, so anyone holding the snapshot
+/// can recompute it
+pub fn analysis_hash(analysis: &GitHubAnalysis) -> String {
+    let canonical = serde_json::to_value(analysis)
+        .and_then(|value| serde_json::to_vec(&value))
+        .unwrap_or_default();
+
+    Sha256::digest(&canonical).iter().map(|b| format!("{:02x}", b)).collect()
 }
 
-fn create_special_badge_metadata(badge_type: &str, score: u32) -> BadgeMetadata {
-    BadgeMetadata {
-        image_url: format!("/badges/special/{}.svg", badge_type),
-        animation_url: Some(format!("/badges/special/{}_animated.gif", badge_type)),
-        attributes: vec![
-            BadgeAttribute {
-                trait_type: "Special Type".to_string(),
-                value: badge_type.to_string(),
-                display_type: None,
-            },
-            BadgeAttribute {
-                trait_type: "Achievement Score".to_string(),
-                value: score.to_string(),
-                display_type: Some("number".to_string()),
-            },
-        ],
-        rarity_score: score,
+/// Fill in where badge evidence came from: the analysis snapshot and the rule set version,
+/// which is also stamped on the badge itself
+pub fn stamp_evidence(badges: &mut [Badge], analysis: &GitHubAnalysis, analysis_hash: &str, rule_version: u32) {
+    for badge in badges.iter_mut() {
+        badge.rule_version = Some(rule_version);
+        let evidence = badge.evidence.get_or_insert_with(|| BadgeEvidence::from_inputs(&[]));
+        evidence.rule_version = rule_version;
+        evidence.analyzed_at = analysis.analyzed_at;
+        evidence.analysis_hash = analysis_hash.to_string();
     }
 }
 
-fn calculate_open_source_score(analysis: &GitHubAnalysis) -> u32 {
-    let public_repos = analysis.repositories.iter()
-        .filter(|r| !r.is_private && !r.is_fork)
-        .count() as u32;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let fork_factor = analysis.total_forks_received * 2;
-    let star_factor = analysis.total_stars_received;
+    fn tier(volume: u32, repos: u32, recent: u32) -> Option<BadgeTier> {
+        language_tier(language_score(volume, repos, recent)).map(|(tier, _)| tier)
+    }
 
-    public_repos * 5 + fork_factor + star_factor
-}
+    fn volumes() -> impl Iterator<Item = u32> {
+        (0..=200_000).step_by(97).chain((200_000..=u32::MAX - 1_000_000).step_by(999_983)).chain([u32::MAX])
+    }
 
-fn extract_languages_from_repos(repositories: &[crate::models::Repository]) -> HashMap<String, u32> {
-    let mut languages = HashMap::new();
+    #[test]
+    fn more_volume_never_lowers_the_tier() {
+        for (repos, recent) in [(1, 0), (1, 1), (5, 2), (20, 10), (60, 12)] {
+            let mut previous = None;
+            for volume in volumes() {
+                let current = tier(volume, repos, recent);
+                assert!(current >= previous, "volume {} with {} repos dropped from {:?} to {:?}", volume, repos, previous, current);
+                previous = current;
+            }
+        }
+    }
 
-    for repo in repositories {
-        if let Some(lang) = &repo.language {
-            if !lang.is_empty() {
-                *languages.entry(lang.clone()).or_insert(0) += repo.size;
+    #[test]
+    fn more_repos_never_lowers_the_tier() {
+        for volume in [MIN_LANGUAGE_VOLUME, 3_000, 20_000, 1_000_000] {
+            for recent in [0, 1, 10] {
+                let mut previous = None;
+                for repos in 1..=100 {
+                    let current = tier(volume, repos, recent);
+                    assert!(current >= previous, "{} repos at volume {} dropped from {:?} to {:?}", repos, volume, previous, current);
+                    previous = current;
+                }
             }
         }
     }
 
-    languages
-}
+    #[test]
+    fn more_recent_repos_never_lowers_the_tier() {
+        for volume in [MIN_LANGUAGE_VOLUME, 3_000, 20_000, 1_000_000] {
+            for repos in [1, 5, 50] {
+                let mut previous = None;
+                for recent in 0..=repos {
+                    let current = tier(volume, repos, recent);
+                    assert!(current >= previous, "{} recent repos at volume {} dropped from {:?} to {:?}", recent, volume, previous, current);
+                    previous = current;
+                }
+            }
+        }
+    }
 
-pub fn calculate_reputation_score(badges: &[Badge]) -> u64 {
-    badges.iter()
-        .map(|badge| badge.tier.get_points() as u64)
-        .sum()
-}
\ No newline at end of file
+    #[test]
+    fn every_eligible_volume_gets_a_tier() {
+        for volume in volumes().filter(|v| *v >= MIN_LANGUAGE_VOLUME) {
+            assert!(tier(volume, 1, 0).is_some(), "volume {} in one repo got no tier", volume);
+        }
+    }
+
+    #[test]
+    fn large_single_repo_and_spread_usage_get_a_tier() {
+        assert!(tier(20_000, 1, 0).is_some());
+        assert!(tier(3_000, 5, 0).is_some());
+    }
+
+    fn badge(id: &str, tier: BadgeTier, earned_at: u64, nft_token_id: Option<u64>) -> Badge {
+        Badge {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            category: BadgeCategory::Language("C++".to_string()),
+            tier,
+            earned_at,
+            criteria_met: Vec::new(),
+            score_achieved: 0,
+            upgraded_at: None,
+            tier_history: None,
+            nft_token_id,
+            at_risk_since: None,
+            season: None,
+            rule_version: None,
+            last_confirmed_at: None,
+            evidence: None,
+            metadata: BadgeMetadata {
+                image_url: String::new(),
+                animation_url: None,
+                attributes: Vec::new(),
+                rarity_score: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn language_spellings_share_one_badge_id() {
+        for spelling in ["C++", "c++", "cpp", " C++ ", "cplusplus"] {
+            assert_eq!(badge_ids::language_badge_id(spelling), "lang_cpp", "{:?}", spelling);
+        }
+        assert_eq!(badge_ids::language_badge_id("C#"), "lang_csharp");
+        assert_eq!(badge_ids::language_badge_id("c sharp"), "lang_csharp");
+        assert_eq!(badge_ids::language_badge_id("C"), "lang_c");
+        assert_eq!(badge_ids::language_badge_id("F#"), "lang_fsharp");
+        assert_eq!(badge_ids::language_badge_id("Visual Basic .NET"), "lang_visual_basic_net");
+        assert_eq!(badge_ids::language_badge_id("golang"), badge_ids::language_badge_id("Go"));
+    }
+
+    #[test]
+    fn legacy_ids_are_canonicalized() {
+        assert_eq!(badge_ids::canonical_badge_id("lang_c++"), "lang_cpp");
+        assert_eq!(badge_ids::canonical_badge_id("lang_C#"), "lang_csharp");
+        assert_eq!(badge_ids::canonical_badge_id("ai_expert_JavaScript"), "ai_expert_javascript");
+        assert_eq!(badge_ids::canonical_badge_id("first_commit"), "first_commit");
+    }
+
+    #[test]
+    fn merged_duplicates_keep_the_highest_tier_and_earliest_earned_at() {
+        let mut badges = vec![
+            badge("lang_c++", BadgeTier::Bronze1, 100, Some(1)),
+            badge("lang_cpp", BadgeTier::Gold1, 200, Some(2)),
+            badge("lang_csharp", BadgeTier::Silver1, 300, None),
+        ];
+
+        let (changed, merged) = canonicalize_badge_ids(&mut badges);
+
+        assert!(changed);
+        assert_eq!(badges.len(), 2);
+        let cpp = badges.iter().find(|b| b.id == "lang_cpp").unwrap();
+        assert_eq!(cpp.tier, BadgeTier::Gold1);
+        assert_eq!(cpp.earned_at, 100);
+        assert_eq!(cpp.nft_token_id, Some(2));
+        assert_eq!(merged.orphaned, vec![1]);
+        assert!(merged.relinked.is_empty());
+    }
+
+    #[test]
+    fn merged_duplicate_hands_its_nft_to_a_kept_badge_without_one() {
+        let mut badges = vec![
+            badge("lang_cpp", BadgeTier::Silver1, 50, None),
+            badge("lang_c++", BadgeTier::Bronze1, 10, Some(7)),
+        ];
+
+        let (changed, merged) = canonicalize_badge_ids(&mut badges);
+
+        assert!(changed);
+        assert_eq!(badges.len(), 1);
+        assert_eq!(badges[0].tier, BadgeTier::Silver1);
+        assert_eq!(badges[0].earned_at, 10);
+        assert_eq!(badges[0].nft_token_id, Some(7));
+        assert_eq!(merged.relinked, vec!["lang_cpp".to_string()]);
+        assert!(merged.orphaned.is_empty());
+    }
+
+    #[test]
+    fn canonical_badges_are_left_alone() {
+        let mut badges = vec![badge("lang_cpp", BadgeTier::Gold1, 1, Some(3)), badge("lang_c", BadgeTier::Bronze1, 2, None)];
+        let (changed, merged) = canonicalize_badge_ids(&mut badges);
+        assert!(!changed);
+        assert_eq!(badges.len(), 2);
+        assert!(merged.relinked.is_empty() && merged.orphaned.is_empty());
+    }
+
+    #[test]
+    fn upgrade_keeps_earned_at_and_sets_upgraded_at() {
+        let mut held = vec![badge("lang_rust", BadgeTier::Bronze2, 100, Some(9))];
+        let generated = vec![badge("lang_rust", BadgeTier::Silver1, 500, None)];
+
+        let progress = merge_badge_progress(&mut held, generated, 500);
+
+        assert_eq!(progress.upgraded, vec!["lang_rust".to_string()]);
+        assert!(progress.awarded.is_empty());
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].tier, BadgeTier::Silver1);
+        assert_eq!(held[0].earned_at, 100);
+        assert_eq!(held[0].upgraded_at, Some(500));
+        assert_eq!(held[0].nft_token_id, Some(9));
+        let tiers: Vec<BadgeTier> = held[0].tier_history.iter().flatten().map(|c| c.tier.clone()).collect();
+        assert_eq!(tiers, vec![BadgeTier::Bronze2, BadgeTier::Silver1]);
+    }
+
+    #[test]
+    fn same_tier_badge_is_confirmed_not_duplicated() {
+        let mut held = vec![badge("lang_rust", BadgeTier::Silver1, 100, None)];
+        let mut fresh = badge("lang_rust", BadgeTier::Silver1, 800, None);
+        fresh.rule_version = Some(3);
+
+        let progress = merge_badge_progress(&mut held, vec![fresh], 800);
+
+        assert!(progress.awarded.is_empty() && progress.upgraded.is_empty());
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].earned_at, 100);
+        assert_eq!(held[0].upgraded_at, None);
+        assert_eq!(held[0].rule_version, Some(3));
+        assert_eq!(held[0].last_confirmed_at, Some(800));
+    }
+
+    #[test]
+    fn new_badge_is_awarded_with_its_first_tier_in_history() {
+        let mut held = vec![badge("lang_rust", BadgeTier::Silver1, 100, None)];
+        let progress = merge_badge_progress(&mut held, vec![badge("lang_go", BadgeTier::Bronze1, 200, None)], 200);
+
+        assert_eq!(progress.awarded, vec!["lang_go".to_string()]);
+        assert_eq!(held.len(), 2);
+        assert_eq!(held[1].tier_history.as_ref().map(Vec::len), Some(1));
+    }
+
+    const DAY_NS: u64 = 24 * 3600 * 1_000_000_000;
+
+    fn policy(mode: RevocationMode, grace_days: u64) -> BadgeRevocationPolicy {
+        BadgeRevocationPolicy { mode, grace_period_secs: grace_days * 24 * 3600 }
+    }
+
+    #[test]
+    fn lower_fresh_tier_downgrades_the_held_badge() {
+        let mut held = vec![badge("lang_rust", BadgeTier::Gold1, 100, Some(4))];
+        let generated = vec![badge("lang_rust", BadgeTier::Silver2, 900, None)];
+
+        let reconciliation = reconcile_badges(&mut held, &generated, &policy(RevocationMode::Downgrade, 0), |_| true, 900);
+
+        assert_eq!(reconciliation.downgraded, vec!["lang_rust".to_string()]);
+        assert_eq!(held[0].tier, BadgeTier::Silver2);
+        assert_eq!(held[0].earned_at, 100);
+        assert_eq!(held[0].nft_token_id, Some(4));
+    }
+
+    #[test]
+    fn never_mode_leaves_badges_alone() {
+        let mut held = vec![badge("lang_rust", BadgeTier::Gold1, 100, None)];
+        let generated = vec![badge("lang_rust", BadgeTier::Bronze1, 900, None)];
+
+        let reconciliation = reconcile_badges(&mut held, &generated, &policy(RevocationMode::Never, 0), |_| true, 900);
+
+        assert!(reconciliation.downgraded.is_empty() && reconciliation.revoked.is_empty());
+        assert_eq!(held[0].tier, BadgeTier::Gold1);
+    }
+
+    #[test]
+    fn unearned_badge_is_revoked_only_after_the_grace_period() {
+        let mut held = vec![badge("lang_rust", BadgeTier::Silver1, 0, Some(5))];
+        let policy = policy(RevocationMode::RevokeAfterGrace, 30);
+
+        // First sync without the badge starts the grace period
+        let first = reconcile_badges(&mut held, &[], &policy, |_| true, 10 * DAY_NS);
+        assert!(first.revoked.is_empty());
+        assert_eq!(first.unsupported, vec!["lang_rust".to_string()]);
+        assert_eq!(held[0].at_risk_since, Some(10 * DAY_NS));
+
+        let within = reconcile_badges(&mut held, &[], &policy, |_| true, 39 * DAY_NS);
+        assert!(within.revoked.is_empty());
+        assert_eq!(held.len(), 1);
+
+        let past = reconcile_badges(&mut held, &[], &policy, |_| true, 40 * DAY_NS);
+        assert!(held.is_empty());
+        assert_eq!(past.revoked.len(), 1);
+        assert_eq!(past.revoked[0].badge.nft_token_id, Some(5));
+    }
+
+    #[test]
+    fn earning_the_badge_again_clears_the_grace_period() {
+        let mut held = vec![badge("lang_rust", BadgeTier::Silver1, 0, None)];
+        let policy = policy(RevocationMode::RevokeAfterGrace, 30);
+
+        reconcile_badges(&mut held, &[], &policy, |_| true, DAY_NS);
+        let generated = vec![badge("lang_rust", BadgeTier::Silver1, 2 * DAY_NS, None)];
+        reconcile_badges(&mut held, &generated, &policy, |_| true, 2 * DAY_NS);
+        assert_eq!(held[0].at_risk_since, None);
+
+        reconcile_badges(&mut held, &[], &policy, |_| true, 40 * DAY_NS);
+        assert_eq!(held.len(), 1);
+    }
+
+    #[test]
+    fn seasonal_badges_are_never_reconciled_away() {
+        let mut seasonal = badge("season_2026_spring_commits", BadgeTier::Gold1, 0, Some(6));
+        seasonal.season = Some("2026_spring".to_string());
+        let mut held = vec![seasonal, badge("ai_quality_master", BadgeTier::Gold1, 0, None)];
+        let policy = policy(RevocationMode::RevokeAfterGrace, 0);
+
+        let reconciliation = reconcile_badges(&mut held, &[], &policy, |b| reevaluated_by_analysis(b, false), 100 * DAY_NS);
+
+        assert!(reconciliation.revoked.is_empty());
+        assert_eq!(held.len(), 2);
+        assert!(held.iter().all(|b| b.at_risk_since.is_none()));
+
+        // Once the LLM did evaluate the profile, unconfirmed AI badges are reconciled like any other
+        let reconciliation = reconcile_badges(&mut held, &[], &policy, |b| reevaluated_by_analysis(b, true), 100 * DAY_NS);
+        assert_eq!(reconciliation.revoked.len(), 1);
+        assert_eq!(held.len(), 1);
+        assert!(held[0].season.is_some());
+    }
+}
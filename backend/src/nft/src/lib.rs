@@ -293,6 +293,56 @@ fn mint(to: Principal, metadata_json: String) -> Result<u64, String> {
     Ok(token_id)
 }
 
+// Replace a token's display metadata, e.g. after its badge was upgraded (only callable by backend canister)
+#[update(guard = "is_backend_canister")]
+fn update_metadata(token_id: u64, metadata_json: String) -> Result<(), String> {
+    let metadata: TokenMetadata = serde_json::from_str(&metadata_json)
+        .map_err(|e| format!("Invalid metadata JSON: {}", e))?;
+
+    TOKENS.with(|tokens| {
+        let mut tokens = tokens.borrow_mut();
+        let existing = tokens.get(&token_id)
+            .ok_or_else(|| format!("Token {} not found", token_id))?;
+
+        tokens.insert(token_id, TokenMetadata {
+            token_id,
+            name: metadata.name,
+            description: metadata.description,
+            image: metadata.image,
+            attributes: metadata.attributes,
+            created_at: existing.created_at,
+        });
+
+        Ok(())
+    })
+}
+
+// Burn a revoked badge: the owner loses it, the metadata is kept with the reason (only callable by backend canister)
+#[update(guard = "is_backend_canister")]
+fn burn(token_id: u64, reason: String) -> Result<(), String> {
+    let owner = OWNERS.with(|owners| owners.borrow_mut().remove(&token_id))
+        .ok_or_else(|| format!("Token {} not found or already burned", token_id))?;
+
+    BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
+        let current_balance = balances.get(&owner).unwrap_or(0);
+        balances.insert(owner, current_balance.saturating_sub(1));
+    });
+
+    TOKENS.with(|tokens| {
+        let mut tokens = tokens.borrow_mut();
+        if let Some(mut metadata) = tokens.get(&token_id) {
+            metadata.attributes.push(Attribute {
+                name: "revoked_reason".to_string(),
+                value: reason,
+            });
+            tokens.insert(token_id, metadata);
+        }
+    });
+
+    Ok(())
+}
+
 fn is_backend_canister() -> Result<(), String> {
     let backend_canister = BACKEND_CANISTER_ID.with(|id| *id.borrow())
         .ok_or("Backend canister not configured")?;
@@ -300,7 +350,7 @@ fn is_backend_canister() -> Result<(), String> {
     if ic_cdk::caller() == backend_canister {
         Ok(())
     } else {
-        Err("Only backend canister can mint tokens".to_string())
+        Err("Only backend canister can manage tokens".to_string())
     }
 }
 
@@ -51,7 +51,7 @@ dfx canister call nft icrc7_total_supply
 # Test leaderboard
 echo ""
 echo "8. 🏅 Leaderboard test..."
-dfx canister call backend get_leaderboard '(opt 5)'
+dfx canister call backend get_leaderboard '(null, opt 5)'
 
 # Test badge statistics
 echo ""
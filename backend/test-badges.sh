@@ -25,7 +25,7 @@ dfx canister call backend get_badges '(null)'
 
 # Test leaderboard
 echo "6. Testing leaderboard..."
-dfx canister call backend get_leaderboard '(opt 5)'
+dfx canister call backend get_leaderboard '(null, opt 5)'
 
 # Test stats
 echo "7. Getting system stats..."
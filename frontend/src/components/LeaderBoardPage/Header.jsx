@@ -33,9 +33,9 @@ const Header = () => {
         if (leaderboard && leaderboard.length > 0) {
           const formattedLeaderboard = leaderboard.map((user, index) => ({
             id: index + 1,
-            username: user.github_username || `User ${index + 1}`,
-            rank: `Points: ${user.reputation_score}`,
-            points: user.total_badges,
+            username: user.username || `User ${index + 1}`,
+            rank: `Points: ${user.score}`,
+            points: user.badge_count,
             profile: user
           }));
           setTopUsers(formattedLeaderboard);
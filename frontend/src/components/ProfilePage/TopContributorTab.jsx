@@ -37,11 +37,11 @@ const TopContributorTab = () => {
         if (realLeaderboard && realLeaderboard.length > 0) {
           // Convert canister data to display format
           const formattedData = realLeaderboard.map((user, index) => ({
-            place: index + 1,
-            username: user.github_username || `User${index + 1}`,
-            points: user.reputation_score || 0,
-            badges: user.total_badges || 0,
-            githubData: user.github_data,
+            place: Number(user.rank) || index + 1,
+            username: user.username || `User${index + 1}`,
+            points: Number(user.score) || 0,
+            badges: user.badge_count || 0,
+            githubData: user.avatar_url.length > 0 ? { avatar_url: user.avatar_url[0] } : null,
             isReal: true,
             profile: user
           }));
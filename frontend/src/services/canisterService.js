@@ -81,6 +81,26 @@ const backendIdl = ({ IDL }) => {
     'total_badges': IDL.Nat32,
   });
 
+  const LeaderboardKey = IDL.Record({
+    'score': IDL.Nat64,
+    'principal': IDL.Principal,
+  });
+
+  const LeaderboardEntry = IDL.Record({
+    'principal': IDL.Principal,
+    'username': IDL.Text,
+    'avatar_url': IDL.Opt(IDL.Text),
+    'score': IDL.Nat64,
+    'rank': IDL.Nat64,
+    'badge_count': IDL.Nat32,
+  });
+
+  const LeaderboardPage = IDL.Record({
+    'entries': IDL.Vec(LeaderboardEntry),
+    'next_cursor': IDL.Opt(LeaderboardKey),
+    'total': IDL.Nat64,
+  });
+
   const GitHubOAuthRequest = IDL.Record({
     'code': IDL.Text,
     'state': IDL.Text,
@@ -91,7 +111,7 @@ const backendIdl = ({ IDL }) => {
     'connect_github_oauth': IDL.Func([GitHubOAuthRequest], [IDL.Variant({ 'Ok': UserProfile, 'Err': IDL.Text })], []),
     'get_profile': IDL.Func([IDL.Opt(IDL.Principal)], [IDL.Opt(UserProfile)], ['query']),
     'get_badges': IDL.Func([IDL.Opt(IDL.Principal)], [IDL.Vec(Badge)], ['query']),
-    'get_leaderboard': IDL.Func([IDL.Opt(IDL.Nat32)], [IDL.Vec(UserProfile)], ['query']),
+    'get_leaderboard': IDL.Func([IDL.Opt(LeaderboardKey), IDL.Opt(IDL.Nat32)], [LeaderboardPage], ['query']),
     'sync_github_data': IDL.Func([], [IDL.Variant({ 'Ok': UserProfile, 'Err': IDL.Text })], []),
     'health_check': IDL.Func([], [IDL.Text], ['query']),
   });
@@ -250,8 +270,8 @@ class CanisterService {
     await this.initialize();
 
     try {
-      const leaderboard = await this.backendActor.get_leaderboard([limit]);
-      return leaderboard || [];
+      const page = await this.backendActor.get_leaderboard([], [limit]);
+      return page?.entries || [];
     } catch (error) {
       console.error('❌ Failed to get leaderboard:', error);
       return [];